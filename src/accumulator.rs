@@ -0,0 +1,453 @@
+//! Folds a stream of [`MessagesStreamEvent`]s back into a [`CreateMessagesResponse`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{accumulator::MessagesStreamExt as _, types::*};
+//! # async fn run() {
+//! let client = async_anthropic::Client::default();
+//!
+//! let request = CreateMessagesRequestBuilder::default()
+//!     .model("claude-3-5-sonnet-20241022")
+//!     .messages(vec!["Hello world!".into()])
+//!     .build()
+//!     .unwrap();
+//!
+//! let response = client
+//!     .messages()
+//!     .create_stream(request)
+//!     .await
+//!     .collect_response()
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use std::{collections::BTreeMap, future::Future};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio_stream::{Stream, StreamExt as _};
+
+use crate::{
+    errors::{map_deserialization_error, AnthropicError, DEFAULT_PAYLOAD_LIMIT},
+    types::{
+        ContentBlockDelta, CreateMessagesResponse, MessageContent, MessagesStreamEvent,
+        StructuredResponse, Usage,
+    },
+};
+
+/// Accumulates streaming events into the response `Messages::create` would
+/// have returned for the same request.
+///
+/// Feed every event to [`MessageAccumulator::push`] and call
+/// [`MessageAccumulator::finish`] once the stream is exhausted.
+#[derive(Debug, Clone, Default)]
+pub struct MessageAccumulator {
+    response: CreateMessagesResponse,
+    /// Raw `input_json_delta` fragments per content block index
+    partial_json: BTreeMap<usize, String>,
+}
+
+impl MessageAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The response as accumulated so far
+    ///
+    /// Tool use inputs are only filled in once their content block has stopped.
+    pub fn response(&self) -> &CreateMessagesResponse {
+        &self.response
+    }
+
+    /// Apply a single streaming event
+    pub fn push(&mut self, event: &MessagesStreamEvent) -> Result<(), AnthropicError> {
+        match event {
+            MessagesStreamEvent::MessageStart { message, usage } => {
+                self.response.id = Some(message.id.clone());
+                self.response.model = Some(message.model.clone());
+                self.response.content.clone_from(&message.content);
+                self.response.stop_reason.clone_from(&message.stop_reason);
+                self.response
+                    .stop_sequence
+                    .clone_from(&message.stop_sequence);
+
+                merge_usage(&mut self.response.usage, message.usage.as_ref());
+                merge_usage(&mut self.response.usage, usage.as_ref());
+            }
+            MessagesStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                let index = *index;
+                if index < self.response.content.len() {
+                    self.response.content[index] = content_block.clone();
+                } else if index == self.response.content.len() {
+                    self.response.content.push(content_block.clone());
+                } else {
+                    return Err(AnthropicError::InvalidStream(format!(
+                        "content block {index} started before block {}",
+                        self.response.content.len()
+                    )));
+                }
+            }
+            MessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                self.apply_delta(*index, delta)?;
+            }
+            MessagesStreamEvent::ContentBlockStop { index } => {
+                let Some(json) = self.partial_json.remove(index) else {
+                    return Ok(());
+                };
+
+                let input = match self.response.content.get_mut(*index) {
+                    Some(MessageContent::ToolUse(tool_use)) => &mut tool_use.input,
+                    Some(MessageContent::ServerToolUse(tool_use)) => &mut tool_use.input,
+                    _ => return Err(unexpected_delta(*index, "input_json_delta")),
+                };
+
+                if !json.trim().is_empty() {
+                    *input = serde_json::from_str(&json).map_err(|e| {
+                        map_deserialization_error(e, json.as_bytes(), None, DEFAULT_PAYLOAD_LIMIT)
+                    })?;
+                }
+            }
+            MessagesStreamEvent::MessageDelta { delta, usage } => {
+                if delta.stop_reason.is_some() {
+                    self.response.stop_reason.clone_from(&delta.stop_reason);
+                }
+                if delta.stop_sequence.is_some() {
+                    self.response.stop_sequence.clone_from(&delta.stop_sequence);
+                }
+
+                merge_usage(&mut self.response.usage, usage.as_ref());
+            }
+            MessagesStreamEvent::MessageStop
+            | MessagesStreamEvent::Ping
+            | MessagesStreamEvent::Unknown(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the accumulated response
+    pub fn finish(self) -> CreateMessagesResponse {
+        self.response
+    }
+
+    fn apply_delta(
+        &mut self,
+        index: usize,
+        delta: &ContentBlockDelta,
+    ) -> Result<(), AnthropicError> {
+        let block = self.response.content.get_mut(index);
+
+        match (delta, block) {
+            (ContentBlockDelta::TextDelta { text }, Some(MessageContent::Text(block))) => {
+                block.text.push_str(text);
+            }
+            (
+                ContentBlockDelta::ThinkingDelta { thinking },
+                Some(MessageContent::Thinking(block)),
+            ) => {
+                block.thinking.push_str(thinking);
+            }
+            (
+                ContentBlockDelta::SignatureDelta { signature },
+                Some(MessageContent::Thinking(block)),
+            ) => {
+                block
+                    .signature
+                    .get_or_insert_with(String::new)
+                    .push_str(signature);
+            }
+            (
+                ContentBlockDelta::InputJsonDelta { partial_json },
+                Some(MessageContent::ToolUse(_) | MessageContent::ServerToolUse(_)),
+            ) => {
+                self.partial_json
+                    .entry(index)
+                    .or_default()
+                    .push_str(partial_json);
+            }
+            (ContentBlockDelta::CitationsDelta { citation }, Some(MessageContent::Text(block))) => {
+                block
+                    .citations
+                    .get_or_insert_with(Vec::new)
+                    .push(citation.clone());
+            }
+            // Unknown deltas can not be applied, the block is left as is
+            (ContentBlockDelta::Unknown(_), _) => {}
+            (delta, _) => return Err(unexpected_delta(index, delta_name(delta))),
+        }
+
+        Ok(())
+    }
+}
+
+/// Merges streamed usage into the accumulated usage
+///
+/// Streamed counts are cumulative, so newer values replace older ones.
+pub(crate) fn merge_usage(current: &mut Option<Usage>, update: Option<&Usage>) {
+    let Some(update) = update else {
+        return;
+    };
+
+    match current {
+        Some(current) => {
+            let update = update.clone();
+            current.input_tokens = update.input_tokens.or(current.input_tokens);
+            current.output_tokens = update.output_tokens.or(current.output_tokens);
+            current.cache_creation_input_tokens = update
+                .cache_creation_input_tokens
+                .or(current.cache_creation_input_tokens);
+            current.cache_read_input_tokens = update
+                .cache_read_input_tokens
+                .or(current.cache_read_input_tokens);
+            current.cache_creation = update.cache_creation.or(current.cache_creation.take());
+            current.server_tool_use = update.server_tool_use.or(current.server_tool_use.take());
+            current.service_tier = update.service_tier.or(current.service_tier.take());
+        }
+        None => *current = Some(update.clone()),
+    }
+}
+
+fn delta_name(delta: &ContentBlockDelta) -> &str {
+    match delta {
+        ContentBlockDelta::TextDelta { .. } => "text_delta",
+        ContentBlockDelta::ThinkingDelta { .. } => "thinking_delta",
+        ContentBlockDelta::SignatureDelta { .. } => "signature_delta",
+        ContentBlockDelta::InputJsonDelta { .. } => "input_json_delta",
+        ContentBlockDelta::CitationsDelta { .. } => "citations_delta",
+        ContentBlockDelta::Unknown(delta) => delta
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown delta"),
+    }
+}
+
+fn unexpected_delta(index: usize, delta: &str) -> AnthropicError {
+    AnthropicError::InvalidStream(format!("unexpected {delta} for content block {index}"))
+}
+
+/// Extension methods for streams of [`MessagesStreamEvent`]s, such as
+/// [`crate::types::CreateMessagesResponseStream`]
+pub trait MessagesStreamExt {
+    /// Consumes the stream and accumulates it into a single response
+    ///
+    /// Returns the first error the stream yields.
+    fn collect_response(
+        self,
+    ) -> impl Future<Output = Result<CreateMessagesResponse, AnthropicError>> + Send;
+
+    /// Consumes the stream and parses the accumulated response into `T`
+    ///
+    /// See [`StructuredResponse::from_response`].
+    fn collect_structured<T: DeserializeOwned>(
+        self,
+    ) -> impl Future<Output = Result<StructuredResponse<T>, AnthropicError>> + Send
+    where
+        Self: Sized + Send,
+    {
+        async move { StructuredResponse::from_response(self.collect_response().await?) }
+    }
+}
+
+impl<S> MessagesStreamExt for S
+where
+    S: Stream<Item = Result<MessagesStreamEvent, AnthropicError>> + Send + Unpin,
+{
+    async fn collect_response(mut self) -> Result<CreateMessagesResponse, AnthropicError> {
+        let mut accumulator = MessageAccumulator::new();
+
+        while let Some(event) = self.next().await {
+            accumulator.push(&event?)?;
+        }
+
+        Ok(accumulator.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::{StopReason, Text, Thinking, ToolUse};
+
+    fn accumulate(events: serde_json::Value) -> CreateMessagesResponse {
+        let events: Vec<MessagesStreamEvent> = serde_json::from_value(events).unwrap();
+        let mut accumulator = MessageAccumulator::new();
+
+        for event in &events {
+            accumulator.push(event).unwrap();
+        }
+
+        accumulator.finish()
+    }
+
+    #[test]
+    fn test_accumulates_text_thinking_and_tool_use() {
+        let response = accumulate(json!([
+            {"type": "message_start", "message": {
+                "id": "msg_1", "model": "claude-sonnet-4", "role": "assistant", "content": [],
+                "usage": {"input_tokens": 25, "output_tokens": 1}
+            }},
+            {"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me "}},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "think."}},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig"}},
+            {"type": "content_block_stop", "index": 0},
+            {"type": "content_block_start", "index": 1, "content_block": {"type": "redacted_thinking", "data": "abc"}},
+            {"type": "content_block_stop", "index": 1},
+            {"type": "content_block_start", "index": 2, "content_block": {"type": "text", "text": ""}},
+            {"type": "ping"},
+            {"type": "content_block_delta", "index": 2, "delta": {"type": "text_delta", "text": "Hello"}},
+            {"type": "content_block_delta", "index": 2, "delta": {"type": "text_delta", "text": " world"}},
+            {"type": "content_block_stop", "index": 2},
+            {"type": "content_block_start", "index": 3, "content_block": {
+                "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {}
+            }},
+            {"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": ""}},
+            {"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": "{\"locat"}},
+            {"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": "ion\": \"SF\"}"}},
+            {"type": "content_block_stop", "index": 3},
+            {"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 42}},
+            {"type": "message_stop"}
+        ]));
+
+        assert_eq!(response.id.as_deref(), Some("msg_1"));
+        assert_eq!(response.model.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                input_tokens: Some(25),
+                output_tokens: Some(42),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            response.content,
+            vec![
+                MessageContent::Thinking(Thinking {
+                    thinking: "Let me think.".to_string(),
+                    signature: Some("sig".to_string()),
+                }),
+                MessageContent::RedactedThinking {
+                    data: "abc".to_string()
+                },
+                MessageContent::Text(Text::from("Hello world")),
+                MessageContent::ToolUse(ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "get_weather".to_string(),
+                    input: json!({"location": "SF"}),
+                    cache_control: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_use_without_input_deltas_keeps_start_input() {
+        let response = accumulate(json!([
+            {"type": "content_block_start", "index": 0, "content_block": {
+                "type": "tool_use", "id": "toolu_1", "name": "noop", "input": {}
+            }},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": ""}},
+            {"type": "content_block_stop", "index": 0}
+        ]));
+
+        assert_eq!(response.content[0].as_tool_use().unwrap().input, json!({}));
+    }
+
+    #[test]
+    fn test_accumulates_server_tool_use() {
+        let response = accumulate(json!([
+            {"type": "content_block_start", "index": 0, "content_block": {
+                "type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {}
+            }},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"query\": "}},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "\"rust\"}"}},
+            {"type": "content_block_stop", "index": 0},
+            {"type": "content_block_start", "index": 1, "content_block": {
+                "type": "web_search_tool_result", "tool_use_id": "srvtoolu_1", "content": []
+            }},
+            {"type": "content_block_stop", "index": 1},
+        ]));
+
+        let MessageContent::ServerToolUse(tool_use) = &response.content[0] else {
+            panic!("expected a server tool use, got {:?}", response.content[0]);
+        };
+        assert_eq!(tool_use.input, json!({"query": "rust"}));
+        assert!(matches!(
+            response.content[1],
+            MessageContent::WebSearchToolResult(_)
+        ));
+    }
+
+    #[test]
+    fn test_accumulates_citations() {
+        let citation = json!({
+            "type": "char_location", "cited_text": "The grass is green.", "document_index": 0,
+            "document_title": "Facts", "start_char_index": 0, "end_char_index": 19
+        });
+        let response = accumulate(json!([
+            {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "citations_delta", "citation": citation}},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "The grass is green"}},
+            {"type": "content_block_stop", "index": 0},
+        ]));
+
+        let text = response.content[0].as_text().unwrap();
+        assert_eq!(text.text, "The grass is green");
+        assert_eq!(
+            text.citations,
+            Some(vec![serde_json::from_value(citation).unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_unknown_blocks_and_events_are_kept() {
+        let response = accumulate(json!([
+            {"type": "content_block_start", "index": 0, "content_block": {
+                "type": "mcp_tool_use", "id": "mcptoolu_1", "name": "search", "server_name": "docs", "input": {}
+            }},
+            {"type": "content_block_delta", "index": 0, "delta": {"type": "mcp_delta", "data": "x"}},
+            {"type": "content_block_stop", "index": 0},
+            {"type": "message_annotation", "note": "new"},
+            {"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}},
+            {"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Done"}},
+            {"type": "content_block_stop", "index": 1},
+        ]));
+
+        assert_eq!(
+            response.content,
+            vec![
+                MessageContent::Unknown(json!({
+                    "type": "mcp_tool_use", "id": "mcptoolu_1", "name": "search", "server_name": "docs", "input": {}
+                })),
+                "Done".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mismatched_delta_is_an_error() {
+        let mut accumulator = MessageAccumulator::new();
+        accumulator
+            .push(&MessagesStreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: MessageContent::Text(Text::from("")),
+            })
+            .unwrap();
+
+        let result = accumulator.push(&MessagesStreamEvent::ContentBlockDelta {
+            index: 0,
+            delta: ContentBlockDelta::ThinkingDelta {
+                thinking: "nope".to_string(),
+            },
+        });
+
+        assert!(matches!(result, Err(AnthropicError::InvalidStream(_))));
+    }
+}
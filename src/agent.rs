@@ -0,0 +1,272 @@
+//! Drive a conversation until the model stops requesting tools
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{agent::AgentRunner, tools::ToolRegistry, types::*};
+//! # async fn run(tools: ToolRegistry) {
+//! let client = async_anthropic::Client::default();
+//!
+//! let request = CreateMessagesRequestBuilder::default()
+//!     .model("claude-3-5-sonnet-20241022")
+//!     .messages(vec!["What is the weather like in San Francisco?".into()])
+//!     .build()
+//!     .unwrap();
+//!
+//! let run = AgentRunner::new(&client, tools)
+//!     .with_max_iterations(5)
+//!     .on_turn(|turn| println!("turn {}: {:?}", turn.iteration, turn.response.stop_reason))
+//!     .run(request)
+//!     .await
+//!     .unwrap();
+//!
+//! println!("{:?}", run.final_response.content);
+//! # }
+//! ```
+use std::sync::Arc;
+
+use crate::{
+    accumulator::MessageAccumulator,
+    errors::AnthropicError,
+    messages::Messages,
+    tools::ToolRegistry,
+    types::{
+        CreateMessagesRequest, CreateMessagesResponse, Message, MessageContent, MessageContentList,
+        MessageRole, MessagesStreamEvent, StopReason, Tool, ToolResult, ToolUse, Usage,
+    },
+    Client,
+};
+use tokio_stream::StreamExt as _;
+
+pub const DEFAULT_MAX_ITERATIONS: usize = 10;
+
+type TurnHook = Arc<dyn Fn(&AgentTurn) + Send + Sync>;
+type EventHook = Arc<dyn Fn(&MessagesStreamEvent) + Send + Sync>;
+
+/// Runs the tool use loop: create a message, call the requested tools, send
+/// back the results and repeat until the model ends its turn
+#[derive(Clone)]
+pub struct AgentRunner<'c> {
+    messages: Messages<'c>,
+    tools: ToolRegistry,
+    max_iterations: usize,
+    parallel_tool_calls: bool,
+    streaming: bool,
+    turn_hooks: Vec<TurnHook>,
+    event_hooks: Vec<EventHook>,
+}
+
+/// A single request/response round trip of an [`AgentRunner`]
+#[derive(Debug, Clone)]
+pub struct AgentTurn {
+    /// Starts at 1
+    pub iteration: usize,
+    pub response: CreateMessagesResponse,
+    /// Results of the tools requested in `response`, if any
+    pub tool_results: Vec<ToolResult>,
+}
+
+/// The outcome of [`AgentRunner::run`]
+#[derive(Debug, Clone)]
+pub struct AgentRun {
+    /// The full transcript, including the messages of the initial request
+    pub messages: Vec<Message>,
+    /// The last response of the model
+    ///
+    /// Its `stop_reason` is still `tool_use` if the run was cut off by the
+    /// maximum number of iterations.
+    pub final_response: CreateMessagesResponse,
+    /// Usage summed over all turns
+    pub usage: Usage,
+    pub iterations: usize,
+}
+
+impl<'c> AgentRunner<'c> {
+    pub fn new(client: &'c Client, tools: ToolRegistry) -> Self {
+        Self {
+            messages: client.messages(),
+            tools,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            parallel_tool_calls: true,
+            streaming: false,
+            turn_hooks: Vec::new(),
+            event_hooks: Vec::new(),
+        }
+    }
+
+    /// Maximum number of requests made in a single run
+    #[must_use]
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Call the tools of a turn concurrently (the default) or one by one
+    #[must_use]
+    pub fn with_parallel_tool_calls(mut self, parallel_tool_calls: bool) -> Self {
+        self.parallel_tool_calls = parallel_tool_calls;
+        self
+    }
+
+    /// Use the streaming api for every turn
+    #[must_use]
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Called after every turn, once its tools have been called
+    #[must_use]
+    pub fn on_turn(mut self, hook: impl Fn(&AgentTurn) + Send + Sync + 'static) -> Self {
+        self.turn_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Called for every streamed event, implies [`AgentRunner::with_streaming`]
+    #[must_use]
+    pub fn on_stream_event(
+        mut self,
+        hook: impl Fn(&MessagesStreamEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.streaming = true;
+        self.event_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Run the conversation
+    ///
+    /// The tools of the registry are added to the tools of the request.
+    #[tracing::instrument(skip_all)]
+    pub async fn run(
+        &self,
+        request: impl Into<CreateMessagesRequest>,
+    ) -> Result<AgentRun, AnthropicError> {
+        let mut request = request.into();
+        let tools = self
+            .tools
+            .tools()
+            .into_iter()
+            .filter(|tool| {
+                !request
+                    .tools
+                    .iter()
+                    .any(|existing| tool_name(existing) == tool_name(tool))
+            })
+            .collect::<Vec<_>>();
+        request.tools.extend(tools);
+
+        let mut usage = Usage::default();
+        let mut iteration = 0;
+
+        loop {
+            iteration += 1;
+
+            let response = self.create(request.clone()).await?;
+            if let Some(response_usage) = &response.usage {
+                usage += response_usage;
+            }
+
+            request.messages.push(Message {
+                role: MessageRole::Assistant,
+                content: MessageContentList(response.content.clone()),
+            });
+
+            let tool_uses = response
+                .content
+                .iter()
+                .filter_map(MessageContent::as_tool_use)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let tool_results = match response.stop_reason {
+                Some(StopReason::ToolUse) if !tool_uses.is_empty() => {
+                    self.call_tools(&tool_uses).await
+                }
+                _ => Vec::new(),
+            };
+
+            if !tool_results.is_empty() {
+                request.messages.push(Message {
+                    role: MessageRole::User,
+                    content: MessageContentList(
+                        tool_results.iter().cloned().map(Into::into).collect(),
+                    ),
+                });
+            }
+
+            let turn = AgentTurn {
+                iteration,
+                response,
+                tool_results,
+            };
+            for hook in &self.turn_hooks {
+                hook(&turn);
+            }
+
+            // A paused turn is continued by sending the conversation as is
+            let continues = !turn.tool_results.is_empty()
+                || turn.response.stop_reason == Some(StopReason::PauseTurn);
+
+            if !continues || iteration >= self.max_iterations {
+                if continues {
+                    tracing::warn!("Agent stopped after {iteration} iterations");
+                }
+
+                return Ok(AgentRun {
+                    messages: request.messages,
+                    final_response: turn.response,
+                    usage,
+                    iterations: iteration,
+                });
+            }
+        }
+    }
+
+    async fn create(
+        &self,
+        request: CreateMessagesRequest,
+    ) -> Result<CreateMessagesResponse, AnthropicError> {
+        if !self.streaming {
+            return self.messages.create(request).await;
+        }
+
+        let mut stream = self.messages.create_stream(request).await;
+        let mut accumulator = MessageAccumulator::new();
+
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            for hook in &self.event_hooks {
+                hook(&event);
+            }
+            accumulator.push(&event)?;
+        }
+
+        Ok(accumulator.finish())
+    }
+
+    async fn call_tools(&self, tool_uses: &[ToolUse]) -> Vec<ToolResult> {
+        if self.parallel_tool_calls {
+            self.tools.call_all_concurrently(tool_uses).await
+        } else {
+            self.tools.call_all(tool_uses).await
+        }
+    }
+}
+
+impl std::fmt::Debug for AgentRunner<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentRunner")
+            .field("tools", &self.tools)
+            .field("max_iterations", &self.max_iterations)
+            .field("parallel_tool_calls", &self.parallel_tool_calls)
+            .field("streaming", &self.streaming)
+            .finish_non_exhaustive()
+    }
+}
+
+fn tool_name(tool: &Tool) -> Option<&str> {
+    match tool {
+        Tool::Custom(tool) => Some(&tool.name),
+        _ => None,
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    errors::AnthropicError,
+    response::Response,
+    types::{
+        CreateMessageBatchRequest, DeleteMessageBatchResponse, ListMessageBatchesResponse,
+        MessageBatch, MessageBatchResultsStream, PaginationParams,
+    },
+    Client,
+};
+
+/// The Message Batches API, for processing many messages requests
+/// asynchronously at a discount
+#[derive(Debug, Clone)]
+pub struct Batches<'c> {
+    client: &'c Client,
+}
+
+impl Batches<'_> {
+    pub fn new(client: &Client) -> Batches<'_> {
+        Batches { client }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn create(
+        &self,
+        request: impl Into<CreateMessageBatchRequest>,
+    ) -> Result<MessageBatch, AnthropicError> {
+        Ok(self.create_with_meta(request).await?.data)
+    }
+
+    /// Like [`Batches::create`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn create_with_meta(
+        &self,
+        request: impl Into<CreateMessageBatchRequest>,
+    ) -> Result<Response<MessageBatch>, AnthropicError> {
+        let mut request = request.into();
+        for batch_request in &mut request.requests {
+            batch_request.params.stream = false;
+        }
+
+        self.client
+            .post_with_meta("/v1/messages/batches", request)
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get(&self, batch_id: impl AsRef<str>) -> Result<MessageBatch, AnthropicError> {
+        Ok(self.get_with_meta(batch_id).await?.data)
+    }
+
+    /// Like [`Batches::get`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn get_with_meta(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<Response<MessageBatch>, AnthropicError> {
+        self.client
+            .get_with_meta(&format!("/v1/messages/batches/{}", batch_id.as_ref()))
+            .await
+    }
+
+    /// List batches, most recently created first
+    #[tracing::instrument(skip_all)]
+    pub async fn list(
+        &self,
+        params: &PaginationParams,
+    ) -> Result<ListMessageBatchesResponse, AnthropicError> {
+        Ok(self.list_with_meta(params).await?.data)
+    }
+
+    /// Like [`Batches::list`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn list_with_meta(
+        &self,
+        params: &PaginationParams,
+    ) -> Result<Response<ListMessageBatchesResponse>, AnthropicError> {
+        self.client
+            .get_with_meta(&format!("/v1/messages/batches{}", params.to_query()))
+            .await
+    }
+
+    /// Cancel a batch that is still processing
+    ///
+    /// Requests that were not yet processed will show up as canceled in the
+    /// results.
+    #[tracing::instrument(skip_all)]
+    pub async fn cancel(&self, batch_id: impl AsRef<str>) -> Result<MessageBatch, AnthropicError> {
+        Ok(self.cancel_with_meta(batch_id).await?.data)
+    }
+
+    /// Like [`Batches::cancel`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn cancel_with_meta(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<Response<MessageBatch>, AnthropicError> {
+        self.client
+            .post_with_meta(
+                &format!("/v1/messages/batches/{}/cancel", batch_id.as_ref()),
+                serde_json::Map::new(),
+            )
+            .await
+    }
+
+    /// Delete a batch that has finished processing
+    #[tracing::instrument(skip_all)]
+    pub async fn delete(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<DeleteMessageBatchResponse, AnthropicError> {
+        Ok(self.delete_with_meta(batch_id).await?.data)
+    }
+
+    /// Like [`Batches::delete`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn delete_with_meta(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<Response<DeleteMessageBatchResponse>, AnthropicError> {
+        self.client
+            .delete_with_meta(&format!("/v1/messages/batches/{}", batch_id.as_ref()))
+            .await
+    }
+
+    /// Stream the results of an ended batch, one entry per request
+    ///
+    /// Results are not guaranteed to be in the same order as the requests, use
+    /// the `custom_id` to match them up.
+    #[tracing::instrument(skip_all)]
+    pub async fn results(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<MessageBatchResultsStream, AnthropicError> {
+        Ok(self.results_with_meta(batch_id).await?.data)
+    }
+
+    /// Like [`Batches::results`], returning once the results started along
+    /// with the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn results_with_meta(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<Response<MessageBatchResultsStream>, AnthropicError> {
+        self.client
+            .get_jsonl_with_meta(&format!(
+                "/v1/messages/batches/{}/results",
+                batch_id.as_ref()
+            ))
+            .await
+    }
+}
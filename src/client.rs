@@ -1,22 +1,41 @@
 use backon::{ExponentialBuilder, Retryable as _};
+use bytes::Bytes;
 use derive_builder::Builder;
-use reqwest::StatusCode;
-use reqwest_eventsource::{
-    retry::{ExponentialBackoff, RetryPolicy},
-    Event, EventSource, RequestBuilderExt as _,
+use http::{
+    header::{HeaderMap, ACCEPT, CONTENT_TYPE},
+    Method, StatusCode,
 };
 use secrecy::ExposeSecret;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{pin::Pin, time::Duration};
+use std::{future::Future, pin::Pin, sync::Arc, time::Instant};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio_stream::{Stream, StreamExt as _};
 
 use crate::{
-    errors::{map_deserialization_error, AnthropicError, ApiError, ApiErrorEnvelope},
+    batches::Batches,
+    errors::{
+        map_deserialization_error, AnthropicError, ApiError, ApiErrorEnvelope,
+        DEFAULT_PAYLOAD_LIMIT,
+    },
+    files::Files,
     messages::Messages,
+    middleware::{Middleware, Next},
     models::Models,
+    rate_limit::RateLimiter,
+    response::{request_id, Response, ResponseMeta},
+    retry::{retry_after, DefaultRetryPolicy, Failure, RetryContext, RetryPolicy},
+    sse::{SseDecoder, SseEvent},
+    transport::{collect_body, HttpRequest, HttpResponse, HttpTransport, ResponseBody},
 };
 
+#[cfg(feature = "reqwest")]
+use std::time::Duration;
+
+#[cfg(feature = "reqwest")]
+use crate::transport::ReqwestTransport;
+
 const BASE_URL: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
 
 /// Main entry point for the Anthropic API
 ///
@@ -45,20 +64,36 @@ const BASE_URL: &str = "https://api.anthropic.com";
 #[derive(Clone, Debug, Builder)]
 #[builder(setter(into, strip_option))]
 pub struct Client {
-    #[builder(default)]
-    http_client: reqwest::Client,
-    #[builder(default)]
+    /// Sends the requests, a [`ReqwestTransport`] by default
+    #[cfg_attr(
+        feature = "reqwest",
+        builder(setter(custom), default = default_transport())
+    )]
+    #[cfg_attr(not(feature = "reqwest"), builder(setter(custom)))]
+    transport: Arc<dyn HttpTransport>,
+    /// Runs around the transport, in order
+    #[builder(setter(custom), default)]
+    middleware: Vec<Arc<dyn Middleware>>,
+    #[builder(default = BASE_URL.to_string())]
     base_url: String,
     #[builder(default = default_api_key())]
     api_key: secrecy::SecretString,
-    #[builder(default)]
+    #[builder(default = API_VERSION.to_string())]
     version: String,
     #[builder(default)]
     beta: Option<String>,
     #[builder(default)]
     backoff: ExponentialBuilder,
+    /// Decides which failures are retried, within the limits of `backoff`
+    #[builder(setter(custom), default = default_retry_policy())]
+    retry_policy: Arc<dyn RetryPolicy>,
+    /// Maximum bytes of the payload kept in deserialization errors, 0 to
+    /// never keep it, for instance if it may hold sensitive data
+    #[builder(default = DEFAULT_PAYLOAD_LIMIT)]
+    payload_limit: usize,
 }
 
+#[cfg(feature = "reqwest")]
 impl Default for Client {
     fn default() -> Self {
         // Load backoff settings from configuration
@@ -69,16 +104,28 @@ impl Default for Client {
             .with_max_delay(Duration::from_secs(120));
 
         Self {
-            http_client: reqwest::Client::new(),
+            transport: default_transport(),
+            middleware: Vec::new(),
             api_key: default_api_key(), // Default env?
-            version: "2023-06-01".to_string(),
+            version: API_VERSION.to_string(),
             beta: None,
             base_url: BASE_URL.to_string(),
             backoff,
+            retry_policy: default_retry_policy(),
+            payload_limit: DEFAULT_PAYLOAD_LIMIT,
         }
     }
 }
 
+#[cfg(feature = "reqwest")]
+fn default_transport() -> Arc<dyn HttpTransport> {
+    Arc::new(ReqwestTransport::default())
+}
+
+fn default_retry_policy() -> Arc<dyn RetryPolicy> {
+    Arc::new(DefaultRetryPolicy::default())
+}
+
 fn default_api_key() -> secrecy::SecretString {
     if cfg!(test) {
         return "test".into();
@@ -91,8 +138,45 @@ fn default_api_key() -> secrecy::SecretString {
         .into()
 }
 
+impl ClientBuilder {
+    /// Send requests with a custom transport
+    pub fn transport(&mut self, transport: impl HttpTransport + 'static) -> &mut Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Add a middleware, running after the middleware added before it
+    pub fn middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware
+            .get_or_insert_with(Vec::new)
+            .push(Arc::new(middleware));
+        self
+    }
+
+    /// Decide which failed requests are retried
+    pub fn retry_policy(&mut self, retry_policy: impl RetryPolicy + 'static) -> &mut Self {
+        self.retry_policy = Some(Arc::new(retry_policy));
+        self
+    }
+
+    /// Throttle requests on the client side, see [`RateLimiter`]
+    ///
+    /// The limiter is added to the middleware, after the middleware added
+    /// before it.
+    pub fn rate_limiter(&mut self, rate_limiter: RateLimiter) -> &mut Self {
+        self.middleware(rate_limiter)
+    }
+
+    /// Send requests with a preconfigured reqwest client
+    #[cfg(feature = "reqwest")]
+    pub fn http_client(&mut self, http_client: reqwest::Client) -> &mut Self {
+        self.transport(ReqwestTransport::new(http_client))
+    }
+}
+
 impl Client {
     /// Build a new client from an API key
+    #[cfg(feature = "reqwest")]
     pub fn from_api_key(api_key: impl Into<secrecy::SecretString>) -> Self {
         Self {
             api_key: api_key.into(),
@@ -111,6 +195,30 @@ impl Client {
         self
     }
 
+    /// Set a custom retry policy
+    pub fn with_retry_policy(mut self, retry_policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Arc::new(retry_policy);
+        self
+    }
+
+    /// Set a custom transport
+    pub fn with_transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Add a middleware, running after the existing middleware
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Throttle requests on the client side, running after the existing
+    /// middleware
+    pub fn with_rate_limiter(self, rate_limiter: RateLimiter) -> Self {
+        self.with_middleware(rate_limiter)
+    }
+
     /// Call the messages api
     pub fn messages(&self) -> Messages<'_> {
         Messages::new(self)
@@ -120,8 +228,18 @@ impl Client {
         Models::new(self)
     }
 
-    fn headers(&self) -> reqwest::header::HeaderMap {
-        let mut headers = reqwest::header::HeaderMap::new();
+    /// Call the message batches api
+    pub fn batches(&self) -> Batches<'_> {
+        Batches::new(self)
+    }
+
+    /// Call the files api
+    pub fn files(&self) -> Files<'_> {
+        Files::new(self)
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
         headers.insert("x-api-key", self.api_key.expose_secret().parse().unwrap());
         headers.insert("anthropic-version", self.version.parse().unwrap());
         if let Some(beta_value) = &self.beta {
@@ -142,28 +260,17 @@ impl Client {
     where
         O: DeserializeOwned,
     {
-        let request = || async {
-            let response = self
-                .http_client
-                .get(self.format_url(path))
-                .headers(self.headers())
-                .send()
-                .await
-                .map_err(AnthropicError::Network)?;
-
-            handle_response(response).await
-        };
+        self.send(|| self.request(Method::GET, path, &[]).body(Bytes::new()))
+            .await
+    }
 
-        request
-            .retry(self.backoff)
-            .sleep(tokio::time::sleep)
-            .when(|e| matches!(e, AnthropicError::RateLimit { .. }))
-            .adjust(|err, dur| match err {
-                AnthropicError::RateLimit { retry_after } => {
-                    retry_after.map(Duration::from_secs).or(dur)
-                }
-                _ => dur,
-            })
+    /// Make get request to the API, returning the response metadata along
+    /// with the deserialized body
+    pub async fn get_with_meta<O>(&self, path: &str) -> Result<Response<O>, AnthropicError>
+    where
+        O: DeserializeOwned,
+    {
+        self.send_with_meta(|| self.request(Method::GET, path, &[]).body(Bytes::new()))
             .await
     }
 
@@ -174,195 +281,414 @@ impl Client {
     where
         I: Serialize,
         O: DeserializeOwned,
+    {
+        Ok(self.post_with_meta(path, request).await?.data)
+    }
+
+    /// Make post request to the API, returning the response metadata along
+    /// with the deserialized body
+    pub async fn post_with_meta<I, O>(
+        &self,
+        path: &str,
+        request: I,
+    ) -> Result<Response<O>, AnthropicError>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        let body = Bytes::from(serde_json::to_vec(&request)?);
+
+        self.send_with_meta(|| {
+            self.request(Method::POST, path, &[])
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+        })
+        .await
+    }
+
+    /// Make delete request to the API
+    pub async fn delete<O>(&self, path: &str) -> Result<O, AnthropicError>
+    where
+        O: DeserializeOwned,
+    {
+        self.send(|| self.request(Method::DELETE, path, &[]).body(Bytes::new()))
+            .await
+    }
+
+    /// Make delete request to the API, returning the response metadata along
+    /// with the deserialized body
+    pub async fn delete_with_meta<O>(&self, path: &str) -> Result<Response<O>, AnthropicError>
+    where
+        O: DeserializeOwned,
+    {
+        self.send_with_meta(|| self.request(Method::DELETE, path, &[]).body(Bytes::new()))
+            .await
+    }
+
+    /// Build a request to the API with all headers set
+    ///
+    /// `betas` are sent alongside the beta configured on the client.
+    pub(crate) fn request(
+        &self,
+        method: Method,
+        path: &str,
+        betas: &[&str],
+    ) -> http::request::Builder {
+        let mut headers = self.headers();
+        if !betas.is_empty() {
+            let beta_value = self
+                .beta
+                .iter()
+                .map(String::as_str)
+                .chain(betas.iter().copied())
+                .collect::<Vec<_>>()
+                .join(",");
+            headers.insert("anthropic-beta", beta_value.parse().unwrap());
+        }
+
+        let mut builder = http::Request::builder()
+            .method(method)
+            .uri(self.format_url(path));
+        if let Some(builder_headers) = builder.headers_mut() {
+            builder_headers.extend(headers);
+        }
+        builder
+    }
+
+    /// Send a request created by `request` and deserialize the response
+    ///
+    /// The request is rebuilt for every retry.
+    pub(crate) async fn send<O>(
+        &self,
+        request: impl Fn() -> http::Result<HttpRequest>,
+    ) -> Result<O, AnthropicError>
+    where
+        O: DeserializeOwned,
+    {
+        Ok(self.send_with_meta(request).await?.data)
+    }
+
+    /// Like [`Client::send`], also returning the response metadata
+    pub(crate) async fn send_with_meta<O>(
+        &self,
+        request: impl Fn() -> http::Result<HttpRequest>,
+    ) -> Result<Response<O>, AnthropicError>
+    where
+        O: DeserializeOwned,
     {
         let request = || async {
-            let mut request = self
-                .http_client
-                .post(self.format_url(path))
-                .headers(self.headers())
-                .json(&request);
-
-            if let Some(beta_value) = &self.beta {
-                request = request.header("anthropic-beta", beta_value);
-            }
+            let (meta, response) = ResponseMeta::from_response(self.execute(request()).await?);
+            let data = handle_response(response, self.payload_limit).await?;
 
-            let response = request.send().await.map_err(AnthropicError::Network)?;
+            Ok(Response { data, meta })
+        };
+
+        self.with_retries(request).await
+    }
+
+    /// Send a request created by `request` and return the raw response body
+    /// along with the response metadata
+    pub(crate) async fn send_for_bytes_with_meta(
+        &self,
+        request: impl Fn() -> http::Result<HttpRequest>,
+    ) -> Result<Response<Vec<u8>>, AnthropicError> {
+        let request = || async {
+            let (meta, response) = ResponseMeta::from_response(self.execute(request()).await?);
+            let data = collect_body(response.into_body()).await?;
 
-            handle_response(response).await
+            Ok(Response { data, meta })
         };
 
+        self.with_retries(request).await
+    }
+
+    /// Make a get request to an endpoint returning JSON lines, yielding each
+    /// line as it arrives
+    pub(crate) async fn get_jsonl_with_meta<O>(
+        &self,
+        path: &str,
+    ) -> Result<
+        Response<Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>>,
+        AnthropicError,
+    >
+    where
+        O: DeserializeOwned + Send + 'static,
+    {
+        let response = self
+            .with_retries(|| self.execute(self.request(Method::GET, path, &[]).body(Bytes::new())))
+            .await?;
+        let (meta, response) = ResponseMeta::from_response(response);
+
+        Ok(Response {
+            data: json_lines(response.into_body(), self.payload_limit),
+            meta,
+        })
+    }
+
+    /// Send a request through the middleware and the transport, turning
+    /// error statuses into errors
+    async fn execute(&self, request: http::Result<HttpRequest>) -> Result<HttpResponse, Failure> {
+        let response = Next::new(&self.middleware, self.transport.as_ref())
+            .run(request?)
+            .await?;
+
+        error_for_status(response).await
+    }
+
+    /// Retry failed attempts for as long as the retry policy allows
+    async fn with_retries<O, F, Fut>(&self, request: F) -> Result<O, AnthropicError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<O, Failure>>,
+    {
+        let start = Instant::now();
+        let mut attempt = 0;
+
         request
             .retry(self.backoff)
             .sleep(tokio::time::sleep)
-            .when(|e| matches!(e, AnthropicError::RateLimit { .. }))
-            .adjust(|err, dur| match err {
-                AnthropicError::RateLimit { retry_after } => {
-                    retry_after.map(Duration::from_secs).or(dur)
-                }
-                _ => dur,
+            .adjust(|failure, backoff| {
+                attempt += 1;
+                self.retry_policy.retry(&RetryContext {
+                    attempt,
+                    elapsed: start.elapsed(),
+                    error: &failure.error,
+                    status: failure.status,
+                    retry_after: failure.retry_after,
+                    backoff,
+                })
             })
             .await
+            .map_err(|failure| failure.error)
     }
 
-    pub(crate) async fn post_stream<I, O, const N: usize>(
+    /// Make a streaming post request to the API
+    ///
+    /// Failures to connect are yielded as the only item of the stream.
+    pub(crate) async fn post_stream<I, O>(
         &self,
         path: &str,
         request: I,
-        event_types: [&'static str; N],
     ) -> Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>
     where
         I: Serialize,
         O: DeserializeOwned + Send + 'static,
     {
-        let event_source = self
-            .http_client
-            .post(self.format_url(path))
-            .headers(self.headers())
-            .json(&request)
-            .eventsource()
-            .unwrap();
-
-        stream(event_source, event_types, &self.backoff).await
+        match self.post_stream_with_meta(path, request).await {
+            Ok(response) => response.data,
+            Err(e) => Box::pin(tokio_stream::once(Err(e))),
+        }
     }
-}
-
-struct RetryAfter {
-    backoff: ExponentialBackoff,
-}
 
-impl RetryPolicy for RetryAfter {
-    fn retry(
+    /// Make a streaming post request to the API, returning once the
+    /// response started
+    pub(crate) async fn post_stream_with_meta<I, O>(
         &self,
-        error: &reqwest_eventsource::Error,
-        last_retry: Option<(usize, Duration)>,
-    ) -> Option<Duration> {
-        match error {
-            reqwest_eventsource::Error::InvalidStatusCode(_, response) => response
-                .headers()
-                .get("Retry-After")
-                .and_then(|h| h.to_str().ok())
-                .and_then(|s| s.parse::<u64>().ok())
-                .map(Duration::from_secs),
-            _ => None,
-        }
-        .or(self.backoff.retry(error, last_retry))
-    }
+        path: &str,
+        request: I,
+    ) -> Result<
+        Response<Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>>,
+        AnthropicError,
+    >
+    where
+        I: Serialize,
+        O: DeserializeOwned + Send + 'static,
+    {
+        let body = Bytes::from(serde_json::to_vec(&request)?);
+        let request = || {
+            self.request(Method::POST, path, &[])
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "text/event-stream")
+                .body(body.clone())
+        };
+
+        let response = self.with_retries(|| self.execute(request())).await?;
+        let (meta, response) = ResponseMeta::from_response(response);
 
-    fn set_reconnection_time(&mut self, duration: Duration) {
-        self.backoff.set_reconnection_time(duration);
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(stream(
+            response.into_body(),
+            tx,
+            meta.request_id.clone(),
+            self.payload_limit,
+        ));
+
+        Ok(Response {
+            data: Box::pin(tokio_stream::wrappers::UnboundedReceiverStream::new(rx)),
+            meta,
+        })
     }
 }
 
-async fn handle_response<O>(response: reqwest::Response) -> Result<O, AnthropicError>
+async fn handle_response<O>(
+    response: HttpResponse,
+    payload_limit: usize,
+) -> Result<O, AnthropicError>
 where
     O: DeserializeOwned,
 {
+    let bytes = collect_body(response.into_body()).await?;
+
+    serde_json::from_slice(&bytes)
+        .map_err(|e| map_deserialization_error(e, &bytes, None, payload_limit))
+}
+
+async fn error_for_status(response: HttpResponse) -> Result<HttpResponse, Failure> {
     let status = response.status();
+    if status == StatusCode::OK {
+        return Ok(response);
+    }
+
+    let retry_after = retry_after(response.headers());
+    let request_id = request_id(response.headers());
+    let failure = |error| Failure {
+        error,
+        status: Some(status),
+        retry_after,
+    };
 
     // 529 is the status code for overloaded requests
     let overloaded_status = StatusCode::from_u16(529).expect("529 is a valid status code");
 
-    match status {
-        StatusCode::OK => response.json::<O>().await.map_err(AnthropicError::Network),
-        _ if status == StatusCode::TOO_MANY_REQUESTS || status == overloaded_status => {
-            let retry_after = response
-                .headers()
-                .get("Retry-After")
-                .and_then(|h| h.to_str().ok())
-                .and_then(|s| s.parse::<u64>().ok());
-
-            let text = response.text().await.map_err(AnthropicError::Network)?;
-            tracing::warn!("Rate limited: {text}");
-            Err(AnthropicError::RateLimit { retry_after })
-        }
-        _ => {
-            let text = response.text().await.map_err(AnthropicError::Network)?;
-            match serde_json::from_str::<ApiErrorEnvelope>(&text) {
-                Ok(envelope) => Err(AnthropicError::Api(envelope.error)),
-                Err(_) => Err(AnthropicError::Unknown(text)),
-            }
-        }
+    let text = collect_body(response.into_body()).await?;
+    if status == StatusCode::TOO_MANY_REQUESTS || status == overloaded_status {
+        tracing::warn!("Rate limited: {}", String::from_utf8_lossy(&text));
+        return Err(failure(AnthropicError::RateLimit {
+            retry_after: retry_after.map(|retry_after| retry_after.as_secs()),
+            status,
+            request_id,
+        }));
+    }
+
+    match serde_json::from_slice::<ApiErrorEnvelope>(&text) {
+        Ok(envelope) => Err(failure(AnthropicError::Api(ApiError {
+            status: Some(status),
+            ..envelope.into_error(request_id.as_deref())
+        }))),
+        Err(_) => Err(failure(AnthropicError::Status {
+            status,
+            body: String::from_utf8_lossy(&text).into_owned(),
+            request_id,
+        })),
     }
 }
 
-async fn stream<O, const N: usize>(
-    mut event_source: EventSource,
-    event_types: [&'static str; N],
-    backoff: &ExponentialBuilder,
+fn json_lines<O>(
+    mut body: ResponseBody,
+    payload_limit: usize,
 ) -> Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>
 where
     O: DeserializeOwned + Send + 'static,
 {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
-    let backoff = ExponentialBackoff {
-        start: backoff.min_delay(),
-        factor: backoff.factor() as f64,
-        max_duration: backoff.max_delay(),
-        max_retries: backoff.max_times(),
-    };
-
     tokio::spawn(async move {
-        event_source.set_retry_policy(Box::new(RetryAfter { backoff }));
-        while let Some(ev) = event_source.next().await {
-            tracing::trace!("Streaming event: {ev:?}");
-            match ev {
-                Ok(event) => match event {
-                    Event::Open => continue,
-                    Event::Message(message) => {
-                        let event = message.event.as_str();
-                        let response = if event == "error" {
-                            match serde_json::from_str::<ApiErrorEnvelope>(&message.data) {
-                                Ok(envelope) => Err(AnthropicError::Api(envelope.error)),
-                                Err(_) => match serde_json::from_str::<ApiError>(&message.data) {
-                                    Ok(e) => Err(AnthropicError::Api(e)),
-                                    Err(e) => {
-                                        Err(map_deserialization_error(e, message.data.as_bytes()))
-                                    }
-                                },
-                            }
-                        } else if event_types.contains(&event) {
-                            match serde_json::from_str::<O>(&message.data) {
-                                Ok(output) => Ok(output),
-                                Err(e) => {
-                                    Err(map_deserialization_error(e, message.data.as_bytes()))
-                                }
-                            }
-                        } else {
-                            Err(AnthropicError::StreamTransport(format!(
-                                "unknown event type: {event}"
-                            )))
-                        };
-                        let cancel = response.is_err();
-                        if tx.send(response).is_err() || cancel {
-                            // rx dropped or other error
-                            break;
-                        }
-                    }
-                },
-                Err(reqwest_eventsource::Error::StreamEnded) => break,
-                Err(
-                    reqwest_eventsource::Error::InvalidContentType(_, response)
-                    | reqwest_eventsource::Error::InvalidStatusCode(_, response),
-                ) => {
-                    if tx.send(handle_response(response).await).is_err() {
-                        break;
-                    }
-                }
+        let mut buffer = Vec::new();
+
+        let parse = |line: &[u8]| {
+            serde_json::from_slice::<O>(line)
+                .map_err(|e| map_deserialization_error(e, line, None, payload_limit))
+        };
+
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
                 Err(e) => {
-                    if tx
-                        .send(Err(AnthropicError::StreamTransport(e.to_string())))
-                        .is_err()
-                    {
-                        // rx dropped
-                        break;
-                    }
+                    let _ = tx.send(Err(e));
+                    return;
+                }
+            };
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+                let line = buffer.drain(..=newline).collect::<Vec<_>>();
+                if line.trim_ascii().is_empty() {
+                    continue;
+                }
+                if tx.send(parse(line.trim_ascii())).is_err() {
+                    // rx dropped
+                    return;
                 }
             }
         }
 
-        event_source.close();
+        if !buffer.trim_ascii().is_empty() {
+            let _ = tx.send(parse(buffer.trim_ascii()));
+        }
     });
 
     Box::pin(tokio_stream::wrappers::UnboundedReceiverStream::new(rx))
 }
+
+/// Parse the server-sent events of `body` and send them to `tx`, stopping at
+/// the first error
+///
+/// Every event other than `error` is deserialized into `O`, the type of the
+/// event is in its data as well.
+async fn stream<O>(
+    mut body: ResponseBody,
+    tx: UnboundedSender<Result<O, AnthropicError>>,
+    request_id: Option<String>,
+    payload_limit: usize,
+) where
+    O: DeserializeOwned + Send + 'static,
+{
+    let mut decoder = SseDecoder::new();
+
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        };
+
+        for message in decoder.push(&chunk) {
+            if !send_event(&tx, message, request_id.as_deref(), payload_limit) {
+                return;
+            }
+        }
+    }
+
+    if let Some(message) = decoder.finish() {
+        send_event(&tx, message, request_id.as_deref(), payload_limit);
+    }
+}
+
+/// Returns false if the stream should stop
+fn send_event<O>(
+    tx: &UnboundedSender<Result<O, AnthropicError>>,
+    message: SseEvent,
+    request_id: Option<&str>,
+    payload_limit: usize,
+) -> bool
+where
+    O: DeserializeOwned,
+{
+    tracing::trace!("Streaming event: {message:?}");
+
+    let event = message.event.as_str();
+    let response = if event == "error" {
+        match serde_json::from_str::<ApiErrorEnvelope>(&message.data) {
+            Ok(envelope) => Err(AnthropicError::Api(envelope.into_error(request_id))),
+            Err(_) => match serde_json::from_str::<ApiError>(&message.data) {
+                Ok(e) => Err(AnthropicError::Api(e.with_request_id(request_id))),
+                Err(e) => Err(map_deserialization_error(
+                    e,
+                    message.data.as_bytes(),
+                    Some(event),
+                    payload_limit,
+                )),
+            },
+        }
+    } else {
+        serde_json::from_str::<O>(&message.data).map_err(|e| {
+            map_deserialization_error(e, message.data.as_bytes(), Some(event), payload_limit)
+        })
+    };
+
+    // Stop on errors or if rx was dropped
+    let cancel = response.is_err();
+    tx.send(response).is_ok() && !cancel
+}
@@ -0,0 +1,532 @@
+//! Executing the actions of the computer use tool
+//!
+//! [`crate::types::ToolComputerUse`] only describes the tool to the model.
+//! The tool uses that come back are parsed into a [`ComputerAction`] and
+//! performed by a [`ComputerBackend`], which controls the actual display.
+//!
+//! [`ComputerTool`] ties these together: it answers a [`ToolUse`] with a
+//! [`ToolResult`] holding the output of the action and a screenshot.
+//! [`FakeComputer`] is an in-memory backend for tests.
+//!
+//! Screenshots are sent as is. Keep the display within the dimensions
+//! recommended by the API, otherwise the API downscales the screenshot and
+//! the coordinates of the model no longer match the display.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{computer::*, types::*};
+//! # async fn run(response: CreateMessagesResponse) {
+//! let computer = ComputerTool::new(
+//!     FakeComputer::new(1024, 768),
+//!     ComputerToolVersion::V20250124,
+//! );
+//!
+//! for tool_use in response.content.iter().filter_map(MessageContent::as_tool_use) {
+//!     let result = computer.call(tool_use).await;
+//! }
+//! # }
+//! ```
+use std::{fmt::Display, future::Future, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    errors::AnthropicError,
+    types::{Image, ToolComputerUse, ToolResult, ToolResultContent, ToolUse},
+};
+
+/// The version of the computer use tool, which determines the available
+/// actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputerToolVersion {
+    /// `computer_20241022`
+    V20241022,
+    /// `computer_20250124`, adding scrolling, triple clicks, fine grained
+    /// mouse control, holding keys and waiting
+    V20250124,
+}
+
+impl ComputerToolVersion {
+    pub fn supports(&self, action: &ComputerAction) -> bool {
+        match self {
+            ComputerToolVersion::V20250124 => true,
+            ComputerToolVersion::V20241022 => matches!(
+                action,
+                ComputerAction::Screenshot
+                    | ComputerAction::CursorPosition
+                    | ComputerAction::Key { .. }
+                    | ComputerAction::Type { .. }
+                    | ComputerAction::MouseMove { .. }
+                    | ComputerAction::LeftClick(_)
+                    | ComputerAction::RightClick(_)
+                    | ComputerAction::MiddleClick(_)
+                    | ComputerAction::DoubleClick(_)
+                    | ComputerAction::LeftClickDrag { .. }
+            ),
+        }
+    }
+}
+
+impl Display for ComputerToolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComputerToolVersion::V20241022 => f.write_str("computer_20241022"),
+            ComputerToolVersion::V20250124 => f.write_str("computer_20250124"),
+        }
+    }
+}
+
+impl From<&ToolComputerUse> for ComputerToolVersion {
+    fn from(tool: &ToolComputerUse) -> Self {
+        match tool {
+            ToolComputerUse::ComputerUse20241022(_) => ComputerToolVersion::V20241022,
+            ToolComputerUse::ComputerUse20250124(_) => ComputerToolVersion::V20250124,
+        }
+    }
+}
+
+/// A position on the display in pixels, serialized as `[x, y]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Coordinate(pub u32, pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// A mouse click, at the current position of the cursor unless a coordinate
+/// is given
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Click {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coordinate: Option<Coordinate>,
+    /// Keys to hold during the click, e.g. `shift` or `ctrl+alt`
+    #[serde(default, rename = "text", skip_serializing_if = "Option::is_none")]
+    pub modifiers: Option<String>,
+}
+
+/// An action requested by the model, the input of a computer use tool use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ComputerAction {
+    Screenshot,
+    CursorPosition,
+    /// Press a key or combination of keys, in xdotool syntax, e.g. `ctrl+s`
+    Key {
+        text: String,
+    },
+    Type {
+        text: String,
+    },
+    MouseMove {
+        coordinate: Coordinate,
+    },
+    LeftClick(Click),
+    RightClick(Click),
+    MiddleClick(Click),
+    DoubleClick(Click),
+    TripleClick(Click),
+    /// Drag from the current position of the cursor, or `start_coordinate`,
+    /// to `coordinate`
+    LeftClickDrag {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start_coordinate: Option<Coordinate>,
+        coordinate: Coordinate,
+    },
+    LeftMouseDown,
+    LeftMouseUp,
+    Scroll {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        coordinate: Option<Coordinate>,
+        scroll_direction: ScrollDirection,
+        scroll_amount: u32,
+        /// Keys to hold while scrolling
+        #[serde(default, rename = "text", skip_serializing_if = "Option::is_none")]
+        modifiers: Option<String>,
+    },
+    /// Hold a key for a number of seconds
+    HoldKey {
+        text: String,
+        duration: f64,
+    },
+    /// Wait for a number of seconds
+    Wait {
+        duration: f64,
+    },
+}
+
+impl ComputerAction {
+    /// Parse the input of a tool use, checking the action is available in
+    /// `version`
+    pub fn parse(input: &Value, version: ComputerToolVersion) -> Result<Self, AnthropicError> {
+        let action = ComputerAction::deserialize(input)
+            .map_err(|e| AnthropicError::InvalidComputerAction(e.to_string()))?;
+
+        if !version.supports(&action) {
+            return Err(AnthropicError::InvalidComputerAction(format!(
+                "{} is not supported by {version}",
+                action.name()
+            )));
+        }
+
+        Ok(action)
+    }
+
+    /// The name of the action as used by the API
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComputerAction::Screenshot => "screenshot",
+            ComputerAction::CursorPosition => "cursor_position",
+            ComputerAction::Key { .. } => "key",
+            ComputerAction::Type { .. } => "type",
+            ComputerAction::MouseMove { .. } => "mouse_move",
+            ComputerAction::LeftClick(_) => "left_click",
+            ComputerAction::RightClick(_) => "right_click",
+            ComputerAction::MiddleClick(_) => "middle_click",
+            ComputerAction::DoubleClick(_) => "double_click",
+            ComputerAction::TripleClick(_) => "triple_click",
+            ComputerAction::LeftClickDrag { .. } => "left_click_drag",
+            ComputerAction::LeftMouseDown => "left_mouse_down",
+            ComputerAction::LeftMouseUp => "left_mouse_up",
+            ComputerAction::Scroll { .. } => "scroll",
+            ComputerAction::HoldKey { .. } => "hold_key",
+            ComputerAction::Wait { .. } => "wait",
+        }
+    }
+}
+
+/// Controls a display on behalf of the model
+pub trait ComputerBackend: Send + Sync {
+    /// Error returned by the backend, reported back to the model
+    type Error: Display;
+
+    /// Capture the display as a png, jpeg, gif or webp image
+    fn screenshot(&self) -> impl Future<Output = Result<Vec<u8>, Self::Error>> + Send;
+
+    /// Perform any action but [`ComputerAction::Screenshot`], returning text
+    /// to send back to the model, e.g. the position of the cursor
+    fn perform(
+        &self,
+        action: &ComputerAction,
+    ) -> impl Future<Output = Result<Option<String>, Self::Error>> + Send;
+}
+
+/// Answers computer use tool uses with a [`ComputerBackend`]
+#[derive(Debug)]
+pub struct ComputerTool<B> {
+    backend: B,
+    version: ComputerToolVersion,
+    screenshot_after_action: bool,
+}
+
+impl<B: ComputerBackend> ComputerTool<B> {
+    pub fn new(backend: B, version: ComputerToolVersion) -> Self {
+        Self {
+            backend,
+            version,
+            screenshot_after_action: true,
+        }
+    }
+
+    /// Whether to include a screenshot in the result of every action, so the
+    /// model sees its effect without asking for one; on by default
+    #[must_use]
+    pub fn with_screenshot_after_action(mut self, screenshot_after_action: bool) -> Self {
+        self.screenshot_after_action = screenshot_after_action;
+        self
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Perform the action requested by `tool_use`
+    ///
+    /// Invalid actions and errors of the backend result in a [`ToolResult`]
+    /// with `is_error` set, so the model can correct itself.
+    #[tracing::instrument(skip_all)]
+    pub async fn call(&self, tool_use: &ToolUse) -> ToolResult {
+        let result = self.run(&tool_use.input).await;
+
+        if let Err(error) = &result {
+            tracing::warn!("Computer action failed: {error}");
+        }
+
+        let is_error = result.is_err();
+        ToolResult {
+            tool_use_id: tool_use.id.clone(),
+            content: result.unwrap_or_else(ToolResultContent::from),
+            is_error,
+            cache_control: None,
+        }
+    }
+
+    async fn run(&self, input: &Value) -> Result<ToolResultContent, String> {
+        let action = ComputerAction::parse(input, self.version).map_err(|e| e.to_string())?;
+
+        let mut content = ToolResultContent::default();
+        if action != ComputerAction::Screenshot {
+            let output = self
+                .backend
+                .perform(&action)
+                .await
+                .map_err(|e| e.to_string())?;
+            content.extend(output.map(ToolResultContent::from).unwrap_or_default().0);
+        }
+
+        if action == ComputerAction::Screenshot || self.screenshot_after_action {
+            let screenshot = self.backend.screenshot().await.map_err(|e| e.to_string())?;
+            let image = Image::from_bytes(screenshot).map_err(|e| e.to_string())?;
+            content.push(image.into());
+        }
+
+        Ok(content)
+    }
+}
+
+/// A blank 1x1 png
+const BLANK_PNG: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60, 0x00, 0x02, 0x00,
+    0x00, 0x05, 0x00, 0x01, 0x7a, 0x5e, 0xab, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44,
+    0xae, 0x42, 0x60, 0x82,
+];
+
+/// A headless display kept in memory, for tests
+///
+/// It records every action, tracks the cursor and typed text, and rejects
+/// coordinates outside the display. Screenshots are a blank png unless set
+/// with [`FakeComputer::with_screenshot`].
+#[derive(Debug)]
+pub struct FakeComputer {
+    width: u32,
+    height: u32,
+    screenshot: Vec<u8>,
+    state: Mutex<FakeComputerState>,
+}
+
+#[derive(Debug, Default)]
+struct FakeComputerState {
+    cursor: Coordinate,
+    typed: String,
+    actions: Vec<ComputerAction>,
+}
+
+impl FakeComputer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            screenshot: BLANK_PNG.to_vec(),
+            state: Mutex::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_screenshot(mut self, screenshot: impl Into<Vec<u8>>) -> Self {
+        self.screenshot = screenshot.into();
+        self
+    }
+
+    /// All actions performed so far, including screenshots
+    pub fn actions(&self) -> Vec<ComputerAction> {
+        self.state.lock().unwrap().actions.clone()
+    }
+
+    pub fn cursor(&self) -> Coordinate {
+        self.state.lock().unwrap().cursor
+    }
+
+    /// Everything typed with [`ComputerAction::Type`]
+    pub fn typed_text(&self) -> String {
+        self.state.lock().unwrap().typed.clone()
+    }
+
+    fn check(&self, coordinate: Coordinate) -> Result<Coordinate, String> {
+        if coordinate.0 >= self.width || coordinate.1 >= self.height {
+            return Err(format!(
+                "coordinate ({}, {}) is outside the {}x{} display",
+                coordinate.0, coordinate.1, self.width, self.height
+            ));
+        }
+        Ok(coordinate)
+    }
+}
+
+impl ComputerBackend for FakeComputer {
+    type Error = String;
+
+    async fn screenshot(&self) -> Result<Vec<u8>, String> {
+        let mut state = self.state.lock().unwrap();
+        state.actions.push(ComputerAction::Screenshot);
+        Ok(self.screenshot.clone())
+    }
+
+    async fn perform(&self, action: &ComputerAction) -> Result<Option<String>, String> {
+        let mut state = self.state.lock().unwrap();
+
+        let target = match action {
+            ComputerAction::MouseMove { coordinate }
+            | ComputerAction::LeftClickDrag { coordinate, .. } => Some(*coordinate),
+            ComputerAction::LeftClick(click)
+            | ComputerAction::RightClick(click)
+            | ComputerAction::MiddleClick(click)
+            | ComputerAction::DoubleClick(click)
+            | ComputerAction::TripleClick(click) => click.coordinate,
+            ComputerAction::Scroll { coordinate, .. } => *coordinate,
+            _ => None,
+        };
+        if let ComputerAction::LeftClickDrag {
+            start_coordinate: Some(start),
+            ..
+        } = action
+        {
+            self.check(*start)?;
+        }
+        if let Some(target) = target {
+            state.cursor = self.check(target)?;
+        }
+        if let ComputerAction::Type { text } = action {
+            state.typed.push_str(text);
+        }
+
+        state.actions.push(action.clone());
+
+        Ok(match action {
+            ComputerAction::CursorPosition => {
+                Some(format!("X={},Y={}", state.cursor.0, state.cursor.1))
+            }
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::{ImageMediaType, ImageSource, ToolResultBlock};
+
+    fn tool_use(input: Value) -> ToolUse {
+        ToolUse {
+            id: "toolu_1".to_string(),
+            input,
+            name: "computer".to_string(),
+            cache_control: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_actions() {
+        let v2 = ComputerToolVersion::V20250124;
+
+        assert_eq!(
+            ComputerAction::parse(&json!({"action": "left_click", "coordinate": [10, 20]}), v2)
+                .unwrap(),
+            ComputerAction::LeftClick(Click {
+                coordinate: Some(Coordinate(10, 20)),
+                modifiers: None,
+            })
+        );
+        assert_eq!(
+            ComputerAction::parse(
+                &json!({
+                    "action": "scroll",
+                    "coordinate": [5, 5],
+                    "scroll_direction": "down",
+                    "scroll_amount": 3,
+                    "text": "shift"
+                }),
+                v2
+            )
+            .unwrap(),
+            ComputerAction::Scroll {
+                coordinate: Some(Coordinate(5, 5)),
+                scroll_direction: ScrollDirection::Down,
+                scroll_amount: 3,
+                modifiers: Some("shift".to_string()),
+            }
+        );
+
+        let scroll = json!({"action": "scroll", "scroll_direction": "up", "scroll_amount": 1});
+        let error = ComputerAction::parse(&scroll, ComputerToolVersion::V20241022).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid computer action: scroll is not supported by computer_20241022"
+        );
+
+        assert!(matches!(
+            ComputerAction::parse(&json!({"action": "teleport"}), v2),
+            Err(AnthropicError::InvalidComputerAction(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_call_performs_action() {
+        let computer =
+            ComputerTool::new(FakeComputer::new(1024, 768), ComputerToolVersion::V20241022);
+
+        let result = computer
+            .call(&tool_use(
+                json!({"action": "mouse_move", "coordinate": [100, 200]}),
+            ))
+            .await;
+        assert!(!result.is_error);
+        assert!(matches!(
+            &result.content[..],
+            [ToolResultBlock::Image(Image {
+                source: ImageSource::Base64 {
+                    media_type: ImageMediaType::Png,
+                    ..
+                },
+                ..
+            })]
+        ));
+
+        computer
+            .call(&tool_use(json!({"action": "type", "text": "hello"})))
+            .await;
+        let result = computer
+            .call(&tool_use(json!({"action": "cursor_position"})))
+            .await;
+        assert_eq!(result.content.text(), "X=100,Y=200");
+        assert_eq!(computer.backend().typed_text(), "hello");
+        assert_eq!(computer.backend().actions().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_call_reports_errors() {
+        let computer = ComputerTool::new(FakeComputer::new(10, 10), ComputerToolVersion::V20250124)
+            .with_screenshot_after_action(false);
+
+        let result = computer
+            .call(&tool_use(
+                json!({"action": "left_click", "coordinate": [10, 0]}),
+            ))
+            .await;
+        assert!(result.is_error);
+        assert_eq!(
+            result.content.text(),
+            "coordinate (10, 0) is outside the 10x10 display"
+        );
+
+        let result = computer
+            .call(&tool_use(json!({"action": "left_click"})))
+            .await;
+        assert!(!result.is_error);
+        assert!(result.content.is_empty());
+
+        let result = computer
+            .call(&tool_use(json!({"action": "screenshot"})))
+            .await;
+        assert_eq!(result.content.len(), 1);
+    }
+}
@@ -1,28 +1,166 @@
+use http::StatusCode;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum AnthropicError {
+    #[cfg(feature = "reqwest")]
     #[error("network error: {0}")]
     Network(#[from] reqwest::Error),
 
+    /// A custom [`crate::transport::HttpTransport`] failed to send a request
+    #[error("transport error: {0}")]
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("invalid request: {0}")]
+    InvalidRequest(#[from] http::Error),
+
     #[error("api error: {0}")]
     Api(#[from] ApiError),
 
+    /// A 429 (rate limited) or 529 (overloaded) response
     #[error("rate limited (retry after {} seconds)", retry_after.unwrap_or_default())]
-    RateLimit { retry_after: Option<u64> },
+    RateLimit {
+        retry_after: Option<u64>,
+        status: StatusCode,
+        request_id: Option<String>,
+    },
+
+    /// An error status without an API error in the body, e.g. from a proxy
+    #[error("unexpected status {status}: {body}")]
+    Status {
+        status: StatusCode,
+        body: String,
+        request_id: Option<String>,
+    },
 
     #[error("failed to deserialize response: {0}")]
-    Deserialization(#[from] serde_json::Error),
+    Deserialization(#[from] DeserializationError),
 
     #[error("stream transport error: {0}")]
     StreamTransport(String),
 
-    #[error("unknown error: {0}")]
-    Unknown(String),
+    #[error("invalid stream: {0}")]
+    InvalidStream(String),
+
+    #[error("invalid schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("invalid image: {0}")]
+    InvalidImage(String),
+
+    /// The input of a computer use tool use is not a known action
+    #[error("invalid computer action: {0}")]
+    InvalidComputerAction(String),
+
+    /// The model did not return text matching the requested output format
+    #[error("failed to parse structured output: {source}")]
+    StructuredOutput {
+        /// The text returned by the model
+        raw: String,
+        #[source]
+        source: serde_json::Error,
+    },
 }
 
+impl From<serde_json::Error> for AnthropicError {
+    fn from(error: serde_json::Error) -> Self {
+        AnthropicError::Deserialization(error.into())
+    }
+}
+
+impl AnthropicError {
+    /// Status of the response that failed, if there was one
+    ///
+    /// Errors sent within a stream have no status.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            AnthropicError::Api(error) => error.status,
+            AnthropicError::RateLimit { status, .. } | AnthropicError::Status { status, .. } => {
+                Some(*status)
+            }
+            #[cfg(feature = "reqwest")]
+            AnthropicError::Network(error) => error.status(),
+            _ => None,
+        }
+    }
+
+    /// Id of the failed request, include it when reporting issues to
+    /// Anthropic
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            AnthropicError::Api(error) => error.request_id.as_deref(),
+            AnthropicError::RateLimit { request_id, .. }
+            | AnthropicError::Status { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The kind of error the API returned, from the error type or else the
+    /// status
+    pub fn kind(&self) -> Option<ApiErrorKind> {
+        match self {
+            AnthropicError::Api(error) => Some(error.kind()),
+            _ => self.status().and_then(ApiErrorKind::from_status),
+        }
+    }
+
+    /// Whether the request may succeed when sent again
+    ///
+    /// True for 408, 409, 429 and 5xx statuses, rate limit, overloaded and
+    /// internal api errors, network failures and failures of custom
+    /// transports.
+    pub fn is_retryable(&self) -> bool {
+        self.is_retryable_with_status(None)
+    }
+
+    /// Like [`AnthropicError::is_retryable`], with the status of the response
+    /// for errors that do not carry one
+    pub(crate) fn is_retryable_with_status(&self, status: Option<StatusCode>) -> bool {
+        if let Some(status) = self.status().or(status) {
+            if matches!(status.as_u16(), 408 | 409 | 429) || status.is_server_error() {
+                return true;
+            }
+        }
+
+        match self {
+            AnthropicError::RateLimit { .. } => true,
+            AnthropicError::Api(error) => error.kind().is_retryable(),
+            #[cfg(feature = "reqwest")]
+            AnthropicError::Network(error) => {
+                error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
+            }
+            AnthropicError::Transport(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the prompt, with `max_tokens`, does not fit in the context
+    /// window of the model
+    pub fn is_context_overflow(&self) -> bool {
+        let AnthropicError::Api(error) = self else {
+            return false;
+        };
+        if error.kind() != ApiErrorKind::InvalidRequest {
+            return false;
+        }
+
+        let message = error.message.as_deref().unwrap_or_default().to_lowercase();
+        CONTEXT_OVERFLOW_MESSAGES
+            .iter()
+            .any(|overflow| message.contains(overflow))
+    }
+}
+
+/// Parts of the messages of invalid request errors for prompts exceeding the
+/// context window
+const CONTEXT_OVERFLOW_MESSAGES: &[&str] = &[
+    "prompt is too long",
+    "exceed context limit",
+    "context window",
+];
+
 /// The wire-format envelope for Anthropic API errors.
 ///
 /// ```json
@@ -31,15 +169,27 @@ pub enum AnthropicError {
 ///     "error": {
 ///         "type": "overloaded_error",
 ///         "message": "Overloaded"
-///     }
+///     },
+///     "request_id": "req_011CSHoEeqs5C35K2UUqR7Fy"
 /// }
 /// ```
 ///
 /// The top-level `type` is always `"error"` and is discarded during
-/// deserialization. Only the inner [`ApiError`] is kept.
-#[derive(Debug, Deserialize)]
-pub(crate) struct ApiErrorEnvelope {
+/// deserialization.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiErrorEnvelope {
     pub error: ApiError,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ApiErrorEnvelope {
+    /// The inner error, with the request id of the envelope or, failing
+    /// that, `request_id`
+    pub(crate) fn into_error(self, request_id: Option<&str>) -> ApiError {
+        let request_id = self.request_id.as_deref().or(request_id);
+        self.error.with_request_id(request_id)
+    }
 }
 
 /// An error returned by the Anthropic API.
@@ -54,6 +204,29 @@ pub struct ApiError {
 
     /// Human-readable error message.
     pub message: Option<String>,
+
+    /// Status of the response, `None` for errors sent within a stream.
+    #[serde(skip)]
+    pub status: Option<StatusCode>,
+
+    /// Id of the failed request, from the envelope or the `request-id`
+    /// header. Include it when reporting issues to Anthropic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn kind(&self) -> ApiErrorKind {
+        ApiErrorKind::from(self.error_type.as_str())
+    }
+
+    /// Set the request id, unless it is already known
+    pub(crate) fn with_request_id(mut self, request_id: Option<&str>) -> Self {
+        if self.request_id.is_none() {
+            self.request_id = request_id.map(str::to_string);
+        }
+        self
+    }
 }
 
 impl std::fmt::Display for ApiError {
@@ -63,12 +236,403 @@ impl std::fmt::Display for ApiError {
             "{}: {}",
             self.error_type,
             self.message.as_deref().unwrap_or("(no message)")
-        )
+        )?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request id: {request_id})")?;
+        }
+        Ok(())
     }
 }
 
 impl std::error::Error for ApiError {}
 
-pub(crate) fn map_deserialization_error(e: serde_json::Error, _bytes: &[u8]) -> AnthropicError {
-    AnthropicError::Deserialization(e)
+/// The type of an [`ApiError`]
+///
+/// Unknown types are kept in [`ApiErrorKind::Unknown`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ApiErrorKind {
+    /// 400, the request is malformed or invalid
+    InvalidRequest,
+    /// 401, the api key is missing or invalid
+    Authentication,
+    /// 403, the api key may not use the resource
+    Permission,
+    /// 404
+    NotFound,
+    /// 413, the request exceeds the maximum size in bytes
+    RequestTooLarge,
+    /// 429
+    RateLimit,
+    /// 500, an unexpected error inside the API
+    Api,
+    /// 529, the API is temporarily overloaded
+    Overloaded,
+    Unknown(String),
+}
+
+impl ApiErrorKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ApiErrorKind::InvalidRequest => "invalid_request_error",
+            ApiErrorKind::Authentication => "authentication_error",
+            ApiErrorKind::Permission => "permission_error",
+            ApiErrorKind::NotFound => "not_found_error",
+            ApiErrorKind::RequestTooLarge => "request_too_large",
+            ApiErrorKind::RateLimit => "rate_limit_error",
+            ApiErrorKind::Api => "api_error",
+            ApiErrorKind::Overloaded => "overloaded_error",
+            ApiErrorKind::Unknown(other) => other,
+        }
+    }
+
+    /// The kind the API uses for `status`, if any
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        let kind = match status.as_u16() {
+            400 => ApiErrorKind::InvalidRequest,
+            401 => ApiErrorKind::Authentication,
+            403 => ApiErrorKind::Permission,
+            404 => ApiErrorKind::NotFound,
+            413 => ApiErrorKind::RequestTooLarge,
+            429 => ApiErrorKind::RateLimit,
+            500 => ApiErrorKind::Api,
+            529 => ApiErrorKind::Overloaded,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Rate limit, overloaded and internal api errors
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiErrorKind::RateLimit | ApiErrorKind::Api | ApiErrorKind::Overloaded
+        )
+    }
+}
+
+impl From<&str> for ApiErrorKind {
+    fn from(value: &str) -> Self {
+        match value {
+            "invalid_request_error" => ApiErrorKind::InvalidRequest,
+            "authentication_error" => ApiErrorKind::Authentication,
+            "permission_error" => ApiErrorKind::Permission,
+            "not_found_error" => ApiErrorKind::NotFound,
+            "request_too_large" => ApiErrorKind::RequestTooLarge,
+            "rate_limit_error" => ApiErrorKind::RateLimit,
+            "api_error" => ApiErrorKind::Api,
+            "overloaded_error" => ApiErrorKind::Overloaded,
+            other => ApiErrorKind::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Maximum bytes of the payload kept in a [`DeserializationError`], unless
+/// set otherwise with the `payload_limit` setting of [`Client::builder`]
+///
+/// [`Client::builder`]: crate::Client::builder
+pub const DEFAULT_PAYLOAD_LIMIT: usize = 2048;
+
+/// A payload that did not deserialize, with the context to find out why
+#[derive(Debug)]
+pub struct DeserializationError {
+    pub source: serde_json::Error,
+    /// The payload, truncated to the payload limit of the client, see
+    /// [`DEFAULT_PAYLOAD_LIMIT`]
+    pub payload: Option<String>,
+    /// Whether `payload` was truncated
+    pub truncated: bool,
+    /// JSON path of the failure, e.g. `content[1].type`
+    pub path: Option<String>,
+    /// Name of the server-sent event the payload came with
+    pub event: Option<String>,
+}
+
+impl DeserializationError {
+    pub(crate) fn new(
+        source: serde_json::Error,
+        payload: &[u8],
+        event: Option<&str>,
+        limit: usize,
+    ) -> Self {
+        let truncated = payload.len() > limit;
+        let kept = String::from_utf8_lossy(&payload[..payload.len().min(limit)]);
+
+        Self {
+            path: json_path(payload, source.line(), source.column()),
+            payload: (limit > 0).then(|| kept.into_owned()),
+            truncated,
+            event: event.map(str::to_string),
+            source,
+        }
+    }
+}
+
+impl From<serde_json::Error> for DeserializationError {
+    fn from(source: serde_json::Error) -> Self {
+        Self {
+            source,
+            payload: None,
+            truncated: false,
+            path: None,
+            event: None,
+        }
+    }
+}
+
+impl std::fmt::Display for DeserializationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(path) = &self.path {
+            write!(f, " at `{path}`")?;
+        }
+        if let Some(event) = &self.event {
+            write!(f, " in `{event}` event")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeserializationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+pub(crate) fn map_deserialization_error(
+    e: serde_json::Error,
+    bytes: &[u8],
+    event: Option<&str>,
+    payload_limit: usize,
+) -> AnthropicError {
+    AnthropicError::Deserialization(DeserializationError::new(e, bytes, event, payload_limit))
+}
+
+/// The JSON path of the value at `line` and `column` of `payload`, as
+/// reported by serde_json
+///
+/// Errors for a whole value, like an unknown variant of a tagged enum, are
+/// reported after it ends, and point to the value containing it.
+fn json_path(payload: &[u8], line: usize, column: usize) -> Option<String> {
+    enum Frame {
+        Object { key: Option<String> },
+        Array { index: usize },
+    }
+
+    if line == 0 {
+        return None;
+    }
+
+    // Lines and columns are 1-based
+    let line_start = payload
+        .split_inclusive(|b| *b == b'\n')
+        .take(line - 1)
+        .map(<[u8]>::len)
+        .sum::<usize>();
+    let end = (line_start + column).min(payload.len());
+
+    let mut stack = Vec::new();
+    let mut string: Option<Vec<u8>> = None;
+    let mut escaped = false;
+    let mut expecting_key = false;
+
+    for &byte in &payload[..end] {
+        if let Some(buffer) = &mut string {
+            match byte {
+                _ if escaped => {
+                    escaped = false;
+                    buffer.push(byte);
+                }
+                b'\\' => escaped = true,
+                b'"' => {
+                    let value = string.take().unwrap_or_default();
+                    if let (true, Some(Frame::Object { key })) = (expecting_key, stack.last_mut()) {
+                        *key = Some(String::from_utf8_lossy(&value).into_owned());
+                    }
+                }
+                _ => buffer.push(byte),
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => string = Some(Vec::new()),
+            b'{' => {
+                stack.push(Frame::Object { key: None });
+                expecting_key = true;
+            }
+            b'[' => {
+                stack.push(Frame::Array { index: 0 });
+                expecting_key = false;
+            }
+            b'}' | b']' => {
+                stack.pop();
+                expecting_key = false;
+            }
+            b':' => expecting_key = false,
+            b',' => match stack.last_mut() {
+                Some(Frame::Object { .. }) => expecting_key = true,
+                Some(Frame::Array { index }) => *index += 1,
+                None => {}
+            },
+            _ => {}
+        }
+    }
+
+    let mut path = String::new();
+    for frame in stack {
+        match frame {
+            Frame::Object { key: Some(key) } => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&key);
+            }
+            Frame::Object { key: None } => {}
+            Frame::Array { index } => path.push_str(&format!("[{index}]")),
+        }
+    }
+
+    (!path.is_empty()).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use crate::types::{CreateMessagesResponse, MessagesStreamEvent, Usage};
+
+    use super::*;
+
+    fn deserialization_error<T: DeserializeOwned + std::fmt::Debug>(
+        payload: &str,
+    ) -> DeserializationError {
+        let error = serde_json::from_str::<T>(payload).unwrap_err();
+        DeserializationError::new(
+            error,
+            payload.as_bytes(),
+            Some("message_start"),
+            DEFAULT_PAYLOAD_LIMIT,
+        )
+    }
+
+    fn api_error(error_type: &str, message: &str) -> AnthropicError {
+        AnthropicError::Api(ApiError {
+            error_type: error_type.to_string(),
+            message: Some(message.to_string()),
+            status: StatusCode::from_u16(400).ok(),
+            request_id: Some("req_1".to_string()),
+        })
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let overloaded = api_error("overloaded_error", "Overloaded");
+        assert_eq!(overloaded.kind(), Some(ApiErrorKind::Overloaded));
+        assert_eq!(overloaded.request_id(), Some("req_1"));
+        assert!(overloaded.is_retryable());
+
+        let unknown = api_error("billing_error", "No credits");
+        assert_eq!(
+            unknown.kind(),
+            Some(ApiErrorKind::Unknown("billing_error".to_string()))
+        );
+        assert!(!unknown.is_retryable());
+
+        let status = AnthropicError::Status {
+            status: StatusCode::NOT_FOUND,
+            body: "Not Found".to_string(),
+            request_id: None,
+        };
+        assert_eq!(status.kind(), Some(ApiErrorKind::NotFound));
+        assert_eq!(status.status(), Some(StatusCode::NOT_FOUND));
+        assert!(!status.is_retryable());
+    }
+
+    #[test]
+    fn test_context_overflow() {
+        assert!(api_error(
+            "invalid_request_error",
+            "prompt is too long: 210000 tokens > 200000 maximum"
+        )
+        .is_context_overflow());
+        assert!(api_error(
+            "invalid_request_error",
+            "input length and `max_tokens` exceed context limit: 198000 + 8192 > 200000"
+        )
+        .is_context_overflow());
+        assert!(
+            !api_error("invalid_request_error", "messages: field required").is_context_overflow()
+        );
+        assert!(!api_error("api_error", "prompt is too long").is_context_overflow());
+    }
+
+    #[test]
+    fn test_deserialization_error_context() {
+        let payload = r#"{"id": "msg_1", "content": [{"type": "text", "text": "Hi"}], "usage": {"input_tokens": "many"}}"#;
+        let error = deserialization_error::<CreateMessagesResponse>(payload);
+
+        assert_eq!(error.path.as_deref(), Some("usage.input_tokens"));
+        assert_eq!(error.payload.as_deref(), Some(payload));
+        assert!(!error.truncated);
+        assert!(error
+            .to_string()
+            .ends_with("at `usage.input_tokens` in `message_start` event"));
+
+        let error = deserialization_error::<Usage>(
+            "{\n  \"input_tokens\": 1,\n  \"output_tokens\": \"many\"\n}",
+        );
+        assert_eq!(error.path.as_deref(), Some("output_tokens"));
+    }
+
+    #[test]
+    fn test_deserialization_error_path_in_tagged_values() {
+        let payload = r#"{"id": "msg_1", "content": [{"type": "text", "text": "Hi"}, {"type": "tool_use", "id": 1, "name": "get_weather", "input": {}}]}"#;
+        let error = deserialization_error::<CreateMessagesResponse>(payload);
+        assert_eq!(error.path.as_deref(), Some("content[1].id"));
+
+        let payload = r#"{"id": "msg_1", "content": [{"type": "tool_use", "name": "get_weather", "input": {}}]}"#;
+        let error = deserialization_error::<CreateMessagesResponse>(payload);
+        assert_eq!(error.path.as_deref(), Some("content[0]"));
+
+        let payload = r#"{"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": "many"}}"#;
+        let error = deserialization_error::<MessagesStreamEvent>(payload);
+        assert_eq!(error.path.as_deref(), Some("usage.output_tokens"));
+
+        let payload = r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ["Hi"]}}"#;
+        let error = deserialization_error::<MessagesStreamEvent>(payload);
+        assert_eq!(error.path.as_deref(), Some("content_block.text"));
+    }
+
+    #[test]
+    fn test_deserialization_error_payload_limit() {
+        let payload = br#"{"input_tokens": "many"}"#;
+        let source = || serde_json::from_slice::<Usage>(payload).unwrap_err();
+
+        let error = DeserializationError::new(source(), payload, None, 8);
+        assert_eq!(error.payload.as_deref(), Some(r#"{"input_"#));
+        assert!(error.truncated);
+        assert_eq!(error.path.as_deref(), Some("input_tokens"));
+
+        let error = DeserializationError::new(source(), payload, None, 0);
+        assert_eq!(error.payload, None);
+        assert!(error.truncated);
+    }
+
+    #[test]
+    fn test_json_path() {
+        let payload = br#"{"a": [1, {"b": "c\"]"}], "d": {"e": 2}}"#;
+        let path = |column| json_path(payload, 1, column);
+
+        assert_eq!(path(0), None);
+        assert_eq!(path(8), Some("a[0]".to_string()));
+        assert_eq!(path(20), Some("a[1].b".to_string()));
+        assert_eq!(path(37), Some("d.e".to_string()));
+        assert_eq!(json_path(payload, 0, 0), None);
+    }
 }
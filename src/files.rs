@@ -0,0 +1,269 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use http::{header::CONTENT_TYPE, HeaderValue, Method};
+use tokio::io::{AsyncRead, AsyncReadExt as _};
+
+use crate::{
+    errors::AnthropicError,
+    response::Response,
+    types::{DeleteFileResponse, FileMetadata, ListFilesResponse, PaginationParams},
+    Client,
+};
+
+/// The beta the files api is currently released under
+///
+/// It is sent automatically by [`Files`]. Requests that reference uploaded
+/// files in their content need it as well, configure it with
+/// `ClientBuilder::beta`.
+pub const FILES_API_BETA: &str = "files-api-2025-04-14";
+
+/// The Files API, for uploading documents and images once and referencing
+/// them by id in messages
+///
+/// Uploaded files are referenced with `DocumentSource::File` and
+/// `ImageSource::File`.
+#[derive(Debug, Clone)]
+pub struct Files<'c> {
+    client: &'c Client,
+}
+
+impl Files<'_> {
+    pub fn new(client: &Client) -> Files<'_> {
+        Files { client }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn upload(&self, file: FileUpload) -> Result<FileMetadata, AnthropicError> {
+        Ok(self.upload_with_meta(file).await?.data)
+    }
+
+    /// Like [`Files::upload`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn upload_with_meta(
+        &self,
+        file: FileUpload,
+    ) -> Result<Response<FileMetadata>, AnthropicError> {
+        let (content_type, body) = multipart_body(&file)?;
+
+        self.client
+            .send_with_meta(|| {
+                self.client
+                    .request(Method::POST, "/v1/files", &[FILES_API_BETA])
+                    .header(CONTENT_TYPE, content_type.clone())
+                    .body(body.clone())
+            })
+            .await
+    }
+
+    /// List uploaded files, most recently created first
+    #[tracing::instrument(skip_all)]
+    pub async fn list(
+        &self,
+        params: &PaginationParams,
+    ) -> Result<ListFilesResponse, AnthropicError> {
+        Ok(self.list_with_meta(params).await?.data)
+    }
+
+    /// Like [`Files::list`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn list_with_meta(
+        &self,
+        params: &PaginationParams,
+    ) -> Result<Response<ListFilesResponse>, AnthropicError> {
+        let path = format!("/v1/files{}", params.to_query());
+
+        self.client
+            .send_with_meta(|| {
+                self.client
+                    .request(Method::GET, &path, &[FILES_API_BETA])
+                    .body(Bytes::new())
+            })
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get(&self, file_id: impl AsRef<str>) -> Result<FileMetadata, AnthropicError> {
+        Ok(self.get_with_meta(file_id).await?.data)
+    }
+
+    /// Like [`Files::get`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn get_with_meta(
+        &self,
+        file_id: impl AsRef<str>,
+    ) -> Result<Response<FileMetadata>, AnthropicError> {
+        let path = format!("/v1/files/{}", file_id.as_ref());
+
+        self.client
+            .send_with_meta(|| {
+                self.client
+                    .request(Method::GET, &path, &[FILES_API_BETA])
+                    .body(Bytes::new())
+            })
+            .await
+    }
+
+    /// Download the contents of a file
+    ///
+    /// Only files created by tools are downloadable, see
+    /// [`FileMetadata::downloadable`].
+    #[tracing::instrument(skip_all)]
+    pub async fn download(&self, file_id: impl AsRef<str>) -> Result<Vec<u8>, AnthropicError> {
+        Ok(self.download_with_meta(file_id).await?.data)
+    }
+
+    /// Like [`Files::download`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn download_with_meta(
+        &self,
+        file_id: impl AsRef<str>,
+    ) -> Result<Response<Vec<u8>>, AnthropicError> {
+        let path = format!("/v1/files/{}/content", file_id.as_ref());
+
+        self.client
+            .send_for_bytes_with_meta(|| {
+                self.client
+                    .request(Method::GET, &path, &[FILES_API_BETA])
+                    .body(Bytes::new())
+            })
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn delete(
+        &self,
+        file_id: impl AsRef<str>,
+    ) -> Result<DeleteFileResponse, AnthropicError> {
+        Ok(self.delete_with_meta(file_id).await?.data)
+    }
+
+    /// Like [`Files::delete`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn delete_with_meta(
+        &self,
+        file_id: impl AsRef<str>,
+    ) -> Result<Response<DeleteFileResponse>, AnthropicError> {
+        let path = format!("/v1/files/{}", file_id.as_ref());
+
+        self.client
+            .send_with_meta(|| {
+                self.client
+                    .request(Method::DELETE, &path, &[FILES_API_BETA])
+                    .body(Bytes::new())
+            })
+            .await
+    }
+}
+
+/// A file to upload with [`Files::upload`]
+///
+/// The contents are kept in memory so the upload can be retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileUpload {
+    pub filename: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl FileUpload {
+    pub fn new(
+        filename: impl Into<String>,
+        mime_type: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self {
+            filename: filename.into(),
+            mime_type: mime_type.into(),
+            data: data.into(),
+        }
+    }
+
+    /// Read a file from disk, guessing the mime type from its extension
+    pub async fn from_path(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let data = tokio::fs::read(path).await?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Self::new(filename, guess_mime_type(path), data))
+    }
+
+    /// Read the contents of a file from an async reader until it is exhausted
+    pub async fn from_reader(
+        filename: impl Into<String>,
+        mime_type: impl Into<String>,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> std::io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+
+        Ok(Self::new(filename, mime_type, data))
+    }
+}
+
+fn guess_mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("csv") => "text/csv",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Encode `file` as a `multipart/form-data` body with a single `file` field
+fn multipart_body(file: &FileUpload) -> Result<(HeaderValue, Bytes), AnthropicError> {
+    let mime_type = HeaderValue::from_str(&file.mime_type).map_err(http::Error::from)?;
+
+    // The boundary must not occur in the file itself
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let boundary = (0..)
+        .map(|attempt| format!("async-anthropic-{:032x}", seed.wrapping_add(attempt)))
+        .find(|boundary| {
+            !file
+                .data
+                .windows(boundary.len())
+                .any(|window| window == boundary.as_bytes())
+        })
+        .expect("a free boundary exists");
+
+    // Quotes and newlines would end the header value early
+    let filename = file
+        .filename
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+
+    let mut body = Vec::with_capacity(file.data.len() + 256);
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\nContent-Type: "
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(mime_type.as_bytes());
+    body.extend_from_slice(b"\r\n\r\n");
+    body.extend_from_slice(&file.data);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    let content_type = HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}"))
+        .expect("boundary is a valid header value");
+
+    Ok((content_type, Bytes::from(body)))
+}
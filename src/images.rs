@@ -0,0 +1,157 @@
+//! Downscale images to the dimensions recommended by the API
+//!
+//! Requires the `image` feature.
+//!
+//! The API resizes images larger than [`MAX_LONG_EDGE`] pixels on their long
+//! edge, or over about [`MAX_PIXELS`] pixels in total. Downscaling them
+//! before sending saves bandwidth and avoids the added latency, at no cost in
+//! quality.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{images, types::*};
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let data = tokio::fs::read("screenshot.png").await?;
+//! let image = Image::from_bytes(images::downscale(&data)?)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{borrow::Cow, io::Cursor};
+
+use image::{imageops::FilterType, ImageFormat, ImageReader};
+
+use crate::{
+    errors::AnthropicError,
+    types::{Image, ImageMediaType},
+};
+
+/// The maximum recommended length of the long edge of an image, in pixels
+pub const MAX_LONG_EDGE: u32 = 1568;
+
+/// The maximum recommended number of pixels of an image
+pub const MAX_PIXELS: u32 = 1_150_000;
+
+/// Downscale an image to fit within [`MAX_LONG_EDGE`] and [`MAX_PIXELS`],
+/// keeping its aspect ratio
+///
+/// Images that already fit are returned as is. Jpegs are encoded as jpeg,
+/// other formats as png; animated images keep only their first frame.
+pub fn downscale(data: &[u8]) -> Result<Cow<'_, [u8]>, AnthropicError> {
+    let media_type = ImageMediaType::sniff(data).ok_or_else(|| {
+        AnthropicError::InvalidImage("expected a jpeg, png, gif or webp image".to_string())
+    })?;
+    let format = match media_type {
+        ImageMediaType::Jpeg => ImageFormat::Jpeg,
+        ImageMediaType::Png => ImageFormat::Png,
+        ImageMediaType::Gif => ImageFormat::Gif,
+        ImageMediaType::Webp => ImageFormat::WebP,
+    };
+
+    // Only the header is needed to tell whether the image fits
+    let (width, height) = ImageReader::with_format(Cursor::new(data), format)
+        .into_dimensions()
+        .map_err(invalid_image)?;
+    let Some((new_width, new_height)) = target_dimensions(width, height) else {
+        return Ok(Cow::Borrowed(data));
+    };
+
+    let image = image::load_from_memory_with_format(data, format).map_err(invalid_image)?;
+
+    let output_format = if format == ImageFormat::Jpeg {
+        ImageFormat::Jpeg
+    } else {
+        ImageFormat::Png
+    };
+
+    let mut output = Cursor::new(Vec::new());
+    image
+        .resize_exact(new_width, new_height, FilterType::Lanczos3)
+        .write_to(&mut output, output_format)
+        .map_err(invalid_image)?;
+
+    Ok(Cow::Owned(output.into_inner()))
+}
+
+impl Image {
+    /// Downscale and encode an image, see [`downscale`] and
+    /// [`Image::from_bytes`]
+    pub fn from_bytes_downscaled(data: impl AsRef<[u8]>) -> Result<Self, AnthropicError> {
+        Image::from_bytes(downscale(data.as_ref())?)
+    }
+}
+
+/// The dimensions to downscale to, `None` if the image already fits
+fn target_dimensions(width: u32, height: u32) -> Option<(u32, u32)> {
+    let long_edge = f64::from(width.max(height));
+    let pixels = f64::from(width) * f64::from(height);
+
+    let scale = (f64::from(MAX_LONG_EDGE) / long_edge)
+        .min((f64::from(MAX_PIXELS) / pixels).sqrt())
+        .min(1.0);
+    if scale >= 1.0 {
+        return None;
+    }
+
+    let scaled = |edge: u32| ((f64::from(edge) * scale).floor() as u32).max(1);
+    Some((scaled(width), scaled(height)))
+}
+
+fn invalid_image(error: image::ImageError) -> AnthropicError {
+    AnthropicError::InvalidImage(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImageView as _, RgbImage};
+
+    use super::*;
+
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let mut output = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut output, format)
+            .unwrap();
+        output.into_inner()
+    }
+
+    #[test]
+    fn test_target_dimensions() {
+        assert_eq!(target_dimensions(1000, 1000), None);
+        assert_eq!(target_dimensions(1568, 600), None);
+        // Limited by the long edge
+        assert_eq!(target_dimensions(3136, 200), Some((1568, 100)));
+        // Limited by the number of pixels
+        assert_eq!(target_dimensions(1500, 1500), Some((1072, 1072)));
+    }
+
+    #[test]
+    fn test_downscale() {
+        let small = encode(100, 50, ImageFormat::Png);
+        assert!(matches!(downscale(&small).unwrap(), Cow::Borrowed(_)));
+        // Images that fit are not decoded, only their header is read
+        let truncated = &encode(100, 50, ImageFormat::Png)[..64];
+        assert!(matches!(downscale(truncated).unwrap(), Cow::Borrowed(_)));
+
+        let large = encode(3136, 200, ImageFormat::Jpeg);
+        let downscaled = downscale(&large).unwrap();
+        assert_eq!(
+            ImageMediaType::sniff(&downscaled),
+            Some(ImageMediaType::Jpeg)
+        );
+        let image = image::load_from_memory(&downscaled).unwrap();
+        assert_eq!(image.dimensions(), (1568, 100));
+
+        let large = encode(2000, 1000, ImageFormat::Gif);
+        let downscaled = downscale(&large).unwrap();
+        assert_eq!(
+            ImageMediaType::sniff(&downscaled),
+            Some(ImageMediaType::Png)
+        );
+
+        assert!(matches!(
+            downscale(b"not an image"),
+            Err(AnthropicError::InvalidImage(_))
+        ));
+    }
+}
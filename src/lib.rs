@@ -1,6 +1,26 @@
+pub mod accumulator;
+pub mod agent;
+pub mod batches;
 mod client;
+pub mod computer;
 pub mod errors;
+pub mod files;
+#[cfg(feature = "image")]
+pub mod images;
 pub mod messages;
+pub mod middleware;
 pub mod models;
+pub mod pricing;
+pub mod rate_limit;
+pub mod response;
+mod resume;
+pub mod retry;
+#[cfg(feature = "schemars")]
+pub mod schema;
+mod sse;
+#[macro_use]
+mod tagged;
+pub mod tools;
+pub mod transport;
 pub mod types;
 pub use client::Client;
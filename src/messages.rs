@@ -1,19 +1,44 @@
+#[cfg(feature = "schemars")]
+use crate::types::{JsonOutputFormat, OutputConfig, StructuredResponse};
 use crate::{
     errors::AnthropicError,
-    types::{CreateMessagesRequest, CreateMessagesResponse, CreateMessagesResponseStream},
+    response::Response,
+    resume::resumable_stream,
+    types::{
+        CountMessageTokensRequest, CountMessageTokensResponse, CreateMessagesRequest,
+        CreateMessagesResponse, CreateMessagesResponseStream,
+    },
     Client,
 };
+#[cfg(feature = "schemars")]
+use serde::de::DeserializeOwned;
 
 pub const DEFAULT_MAX_TOKENS: i32 = 2048;
 
 #[derive(Debug, Clone)]
 pub struct Messages<'c> {
     client: &'c Client,
+    max_resumes: usize,
 }
 
 impl Messages<'_> {
     pub fn new(client: &Client) -> Messages<'_> {
-        Messages { client }
+        Messages {
+            client,
+            max_resumes: 0,
+        }
+    }
+
+    /// Resume streams that are cut off by a transport failure, at most
+    /// `max_resumes` times per stream
+    ///
+    /// The request is sent again with the content received so far as the
+    /// last assistant turn, and the continuation is emitted as part of the
+    /// same stream. Note that every resume is billed as a new request.
+    #[must_use]
+    pub fn with_resume(mut self, max_resumes: usize) -> Self {
+        self.max_resumes = max_resumes;
+        self
     }
 
     #[tracing::instrument(skip_all)]
@@ -21,10 +46,20 @@ impl Messages<'_> {
         &self,
         request: impl Into<CreateMessagesRequest>,
     ) -> Result<CreateMessagesResponse, AnthropicError> {
+        Ok(self.create_with_meta(request).await?.data)
+    }
+
+    /// Like [`Messages::create`], also returning the status, request id and
+    /// other metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn create_with_meta(
+        &self,
+        request: impl Into<CreateMessagesRequest>,
+    ) -> Result<Response<CreateMessagesResponse>, AnthropicError> {
         let mut request = request.into();
         request.stream = false;
 
-        self.client.post("/v1/messages", request).await
+        self.client.post_with_meta("/v1/messages", request).await
     }
 
     #[tracing::instrument(skip_all)]
@@ -35,20 +70,126 @@ impl Messages<'_> {
         let mut request = request.into();
         request.stream = true;
 
+        if self.max_resumes > 0 {
+            let stream = self.stream(&request).await;
+            return resumable_stream(self.client.clone(), request, stream, self.max_resumes);
+        }
+
+        self.stream(&request).await
+    }
+
+    /// Like [`Messages::create_stream`], returning once the response started
+    /// along with its metadata
+    ///
+    /// Failures to connect are returned as an error instead of being yielded
+    /// by the stream. With [`Messages::with_resume`], the metadata is that of
+    /// the first response.
+    #[tracing::instrument(skip_all)]
+    pub async fn create_stream_with_meta(
+        &self,
+        request: impl Into<CreateMessagesRequest>,
+    ) -> Result<Response<CreateMessagesResponseStream>, AnthropicError> {
+        let mut request = request.into();
+        request.stream = true;
+
+        let response = self
+            .client
+            .post_stream_with_meta("/v1/messages", &request)
+            .await?;
+
+        if self.max_resumes > 0 {
+            let client = self.client.clone();
+            let max_resumes = self.max_resumes;
+            return Ok(
+                response.map(|stream| resumable_stream(client, request, stream, max_resumes))
+            );
+        }
+
+        Ok(response)
+    }
+
+    pub(crate) async fn stream(
+        &self,
+        request: &CreateMessagesRequest,
+    ) -> CreateMessagesResponseStream {
+        self.client.post_stream("/v1/messages", request).await
+    }
+
+    /// Create a message with `T` as the output format, parsing the response
+    /// into `T`
+    ///
+    /// Requires the `schemars` feature.
+    #[cfg(feature = "schemars")]
+    #[tracing::instrument(skip_all)]
+    pub async fn create_structured<T>(
+        &self,
+        request: impl Into<CreateMessagesRequest>,
+    ) -> Result<StructuredResponse<T>, AnthropicError>
+    where
+        T: schemars::JsonSchema + DeserializeOwned,
+    {
+        let request = with_output_format::<T>(request.into())?;
+        let response = self.create(request).await?;
+
+        StructuredResponse::from_response(response)
+    }
+
+    /// Stream a message with `T` as the output format
+    ///
+    /// Use `MessagesStreamExt::collect_structured` to parse the streamed
+    /// response into `T`.
+    ///
+    /// Requires the `schemars` feature.
+    #[cfg(feature = "schemars")]
+    #[tracing::instrument(skip_all)]
+    pub async fn create_structured_stream<T>(
+        &self,
+        request: impl Into<CreateMessagesRequest>,
+    ) -> Result<CreateMessagesResponseStream, AnthropicError>
+    where
+        T: schemars::JsonSchema,
+    {
+        let request = with_output_format::<T>(request.into())?;
+
+        Ok(self.create_stream(request).await)
+    }
+
+    /// Count the input tokens of a request without creating a message
+    ///
+    /// Accepts a [`CreateMessagesRequest`] as well; fields that do not affect
+    /// the prompt, like `max_tokens` and `stream`, are dropped.
+    #[tracing::instrument(skip_all)]
+    pub async fn count_tokens(
+        &self,
+        request: impl Into<CountMessageTokensRequest>,
+    ) -> Result<CountMessageTokensResponse, AnthropicError> {
+        Ok(self.count_tokens_with_meta(request).await?.data)
+    }
+
+    /// Like [`Messages::count_tokens`], also returning the metadata of the
+    /// response
+    #[tracing::instrument(skip_all)]
+    pub async fn count_tokens_with_meta(
+        &self,
+        request: impl Into<CountMessageTokensRequest>,
+    ) -> Result<Response<CountMessageTokensResponse>, AnthropicError> {
         self.client
-            .post_stream(
-                "/v1/messages",
-                request,
-                [
-                    "ping",
-                    "message_start",
-                    "message_delta",
-                    "message_stop",
-                    "content_block_start",
-                    "content_block_delta",
-                    "content_block_stop",
-                ],
-            )
+            .post_with_meta("/v1/messages/count_tokens", request.into())
             .await
     }
 }
+
+#[cfg(feature = "schemars")]
+fn with_output_format<T: schemars::JsonSchema>(
+    mut request: CreateMessagesRequest,
+) -> Result<CreateMessagesRequest, AnthropicError> {
+    request
+        .output_config
+        .get_or_insert(OutputConfig {
+            effort: None,
+            format: None,
+        })
+        .format = Some(JsonOutputFormat::from_type::<T>()?);
+
+    Ok(request)
+}
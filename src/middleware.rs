@@ -0,0 +1,96 @@
+//! Inspect and modify requests and responses of every endpoint
+//!
+//! Middleware is added with `ClientBuilder::middleware` and runs in the order
+//! it was added, wrapping the [`crate::transport::HttpTransport`]. Each
+//! middleware gets the outgoing request and decides how to call the rest of
+//! the chain with [`Next::run`], so it can modify the request, the response,
+//! or both.
+//!
+//! Middleware runs for every attempt, retries included. Streaming responses
+//! pass through as well: their body is a stream of chunks that can be wrapped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{errors::AnthropicError, middleware::*, transport::*};
+//! # use futures::future::BoxFuture;
+//! #[derive(Debug)]
+//! struct Timing;
+//!
+//! impl Middleware for Timing {
+//!     fn handle<'a>(
+//!         &'a self,
+//!         mut request: HttpRequest,
+//!         next: Next<'a>,
+//!     ) -> BoxFuture<'a, Result<HttpResponse, AnthropicError>> {
+//!         request
+//!             .headers_mut()
+//!             .insert("x-team", "research".parse().unwrap());
+//!
+//!         Box::pin(async move {
+//!             let start = std::time::Instant::now();
+//!             let response = next.run(request).await;
+//!             println!("request took {:?}", start.elapsed());
+//!             response
+//!         })
+//!     }
+//! }
+//!
+//! let client = async_anthropic::Client::builder()
+//!     .middleware(Timing)
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+use crate::{
+    errors::AnthropicError,
+    transport::{HttpRequest, HttpResponse, HttpTransport},
+};
+
+/// Intercepts requests before they are sent and their responses
+pub trait Middleware: std::fmt::Debug + Send + Sync {
+    /// Handle a request, usually by passing it on with [`Next::run`]
+    ///
+    /// Returning without calling `next` short-circuits the chain.
+    fn handle<'a>(
+        &'a self,
+        request: HttpRequest,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<HttpResponse, AnthropicError>>;
+}
+
+/// The rest of the middleware chain, ending in the transport
+#[derive(Debug, Clone, Copy)]
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    transport: &'a dyn HttpTransport,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middleware: &'a [Arc<dyn Middleware>],
+        transport: &'a dyn HttpTransport,
+    ) -> Self {
+        Self {
+            middleware,
+            transport,
+        }
+    }
+
+    /// Pass the request to the next middleware, or send it if there is none
+    pub fn run(self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse, AnthropicError>> {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => middleware.handle(
+                request,
+                Next {
+                    middleware: rest,
+                    transport: self.transport,
+                },
+            ),
+            None => self.transport.send(request),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     errors::AnthropicError,
+    response::Response,
     types::{GetModelResponse, ListModelsResponse},
     Client,
 };
@@ -18,13 +19,28 @@ impl Models<'_> {
 
     #[tracing::instrument(skip_all)]
     pub async fn list(&self) -> Result<ListModelsResponse, AnthropicError> {
-        self.client.get("/v1/models").await
+        Ok(self.list_with_meta().await?.data)
+    }
+
+    /// Like [`Models::list`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn list_with_meta(&self) -> Result<Response<ListModelsResponse>, AnthropicError> {
+        self.client.get_with_meta("/v1/models").await
     }
 
     #[tracing::instrument(skip_all)]
     pub async fn get(&self, model_id: impl AsRef<str>) -> Result<GetModelResponse, AnthropicError> {
+        Ok(self.get_with_meta(model_id).await?.data)
+    }
+
+    /// Like [`Models::get`], also returning the metadata of the response
+    #[tracing::instrument(skip_all)]
+    pub async fn get_with_meta(
+        &self,
+        model_id: impl AsRef<str>,
+    ) -> Result<Response<GetModelResponse>, AnthropicError> {
         self.client
-            .get(&format!("/v1/models/{}", model_id.as_ref()))
+            .get_with_meta(&format!("/v1/models/{}", model_id.as_ref()))
             .await
     }
 }
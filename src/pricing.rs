@@ -0,0 +1,314 @@
+//! Estimate the cost of requests from their [`Usage`]
+//!
+//! Prices are in USD and taken from the public price list; they are an
+//! estimate, not an invoice. Models missing from the table, or with a changed
+//! price, can be added with [`PricingTable::set`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{pricing::*, types::*};
+//! # fn run(response: CreateMessagesResponse) {
+//! let pricing = PricingTable::default()
+//!     .with_model("my-fine-tuned-model", ModelPricing::new(3.0, 15.0));
+//!
+//! if let Some(cost) = pricing.estimate_response(&response) {
+//!     println!("${:.4}", cost.total());
+//! }
+//! # }
+//! ```
+use crate::types::{CreateMessagesResponse, Usage};
+
+/// Price of web search, per request
+pub const DEFAULT_WEB_SEARCH_PRICE: f64 = 0.01;
+
+/// Token prices are discounted by half for message batches
+pub const BATCH_DISCOUNT: f64 = 0.5;
+
+const TOKENS_PER_MILLION: f64 = 1_000_000.0;
+
+/// Token prices of a model, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    /// Writing to the cache with the default 5 minute TTL
+    pub cache_write_5m: f64,
+    /// Writing to the cache with a 1 hour TTL
+    pub cache_write_1h: f64,
+    pub cache_read: f64,
+}
+
+impl ModelPricing {
+    /// Pricing with the standard cache multipliers: 1.25x the input price for
+    /// 5 minute cache writes, 2x for 1 hour cache writes and 0.1x for cache
+    /// reads
+    pub fn new(input: f64, output: f64) -> Self {
+        Self {
+            input,
+            output,
+            cache_write_5m: input * 1.25,
+            cache_write_1h: input * 2.0,
+            cache_read: input * 0.1,
+        }
+    }
+}
+
+/// Estimated cost of a request, in USD
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CostBreakdown {
+    pub input: f64,
+    pub output: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+    pub web_search: f64,
+}
+
+impl CostBreakdown {
+    pub fn total(&self) -> f64 {
+        self.input + self.output + self.cache_write + self.cache_read + self.web_search
+    }
+}
+
+impl std::ops::Add for CostBreakdown {
+    type Output = CostBreakdown;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        CostBreakdown {
+            input: self.input + rhs.input,
+            output: self.output + rhs.output,
+            cache_write: self.cache_write + rhs.cache_write,
+            cache_read: self.cache_read + rhs.cache_read,
+            web_search: self.web_search + rhs.web_search,
+        }
+    }
+}
+
+impl std::ops::AddAssign for CostBreakdown {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+/// Prices per model
+///
+/// Models are looked up by their id or a dated snapshot of it, so
+/// `claude-sonnet-4-20250514` uses the price of `claude-sonnet-4`. Other
+/// models have no price, rather than the price of a related model, so a
+/// table missing a model is noticed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingTable {
+    models: Vec<(String, ModelPricing)>,
+    web_search: f64,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let models = [
+            ("claude-opus-4-6", 5.0, 25.0),
+            ("claude-opus-4-5", 5.0, 25.0),
+            ("claude-opus-4-1", 15.0, 75.0),
+            ("claude-opus-4", 15.0, 75.0),
+            ("claude-sonnet-4-5", 3.0, 15.0),
+            ("claude-sonnet-4", 3.0, 15.0),
+            ("claude-haiku-4-5", 1.0, 5.0),
+            ("claude-3-7-sonnet", 3.0, 15.0),
+            ("claude-3-5-sonnet", 3.0, 15.0),
+            ("claude-3-5-haiku", 0.8, 4.0),
+            ("claude-3-opus", 15.0, 75.0),
+            ("claude-3-sonnet", 3.0, 15.0),
+            ("claude-3-haiku", 0.25, 1.25),
+        ];
+
+        Self {
+            models: models
+                .into_iter()
+                .map(|(model, input, output)| (model.to_string(), ModelPricing::new(input, output)))
+                .collect(),
+            web_search: DEFAULT_WEB_SEARCH_PRICE,
+        }
+    }
+}
+
+impl PricingTable {
+    /// A table without any models
+    pub fn empty() -> Self {
+        Self {
+            models: Vec::new(),
+            web_search: DEFAULT_WEB_SEARCH_PRICE,
+        }
+    }
+
+    /// Add or replace the pricing of a model and its dated snapshots
+    pub fn set(&mut self, model: impl Into<String>, pricing: ModelPricing) -> &mut Self {
+        let model = model.into();
+        match self
+            .models
+            .iter_mut()
+            .find(|(existing, _)| *existing == model)
+        {
+            Some((_, existing)) => *existing = pricing,
+            None => self.models.push((model, pricing)),
+        }
+        self
+    }
+
+    /// Add or replace the pricing of a model and its dated snapshots
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>, pricing: ModelPricing) -> Self {
+        self.set(model, pricing);
+        self
+    }
+
+    /// Price per web search request
+    #[must_use]
+    pub fn with_web_search_price(mut self, price: f64) -> Self {
+        self.web_search = price;
+        self
+    }
+
+    /// The pricing of a model or a dated snapshot of it, like
+    /// `claude-sonnet-4-20250514`
+    pub fn get(&self, model: &str) -> Option<&ModelPricing> {
+        let id = match model.rsplit_once('-') {
+            Some((id, date)) if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) => id,
+            _ => model,
+        };
+
+        self.models
+            .iter()
+            .find(|(existing, _)| existing == model || existing == id)
+            .map(|(_, pricing)| pricing)
+    }
+
+    /// Estimate the cost of `usage`, or `None` if the model is unknown
+    ///
+    /// Usage with the `batch` service tier gets the [`BATCH_DISCOUNT`]. Cache
+    /// writes without a TTL breakdown are priced as 5 minute writes.
+    pub fn estimate(&self, model: &str, usage: &Usage) -> Option<CostBreakdown> {
+        let pricing = self.get(model)?;
+
+        let discount = if usage.service_tier.as_deref() == Some("batch") {
+            BATCH_DISCOUNT
+        } else {
+            1.0
+        };
+        let price = |tokens: Option<u32>, price: f64| {
+            f64::from(tokens.unwrap_or_default()) * price * discount / TOKENS_PER_MILLION
+        };
+
+        let (write_5m, write_1h) = match &usage.cache_creation {
+            Some(cache_creation) => {
+                let write_5m = cache_creation.ephemeral_5m_input_tokens.unwrap_or_default();
+                let write_1h = cache_creation.ephemeral_1h_input_tokens.unwrap_or_default();
+                // Anything not covered by the breakdown uses the default TTL
+                let rest = usage
+                    .cache_creation_input_tokens
+                    .unwrap_or_default()
+                    .saturating_sub(write_5m + write_1h);
+
+                (write_5m + rest, write_1h)
+            }
+            None => (usage.cache_creation_input_tokens.unwrap_or_default(), 0),
+        };
+
+        let web_search_requests = usage
+            .server_tool_use
+            .as_ref()
+            .and_then(|server_tool_use| server_tool_use.web_search_requests)
+            .unwrap_or_default();
+
+        Some(CostBreakdown {
+            input: price(usage.input_tokens, pricing.input),
+            output: price(usage.output_tokens, pricing.output),
+            cache_write: price(Some(write_5m), pricing.cache_write_5m)
+                + price(Some(write_1h), pricing.cache_write_1h),
+            cache_read: price(usage.cache_read_input_tokens, pricing.cache_read),
+            web_search: f64::from(web_search_requests) * self.web_search,
+        })
+    }
+
+    /// Estimate the cost of a response, or `None` if its model is unknown or
+    /// it has no usage
+    pub fn estimate_response(&self, response: &CreateMessagesResponse) -> Option<CostBreakdown> {
+        self.estimate(response.model.as_deref()?, response.usage.as_ref()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{CacheCreation, ServerToolUsage};
+
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_model_lookup() {
+        let pricing = PricingTable::default();
+
+        assert_eq!(pricing.get("claude-opus-4-20250514").unwrap().input, 15.0);
+        assert_eq!(pricing.get("claude-opus-4-5-20251101").unwrap().input, 5.0);
+        assert_eq!(pricing.get("claude-3-5-haiku").unwrap().input, 0.8);
+        assert!(pricing.get("gpt-4").is_none());
+        // Unknown models are not priced like a related model
+        assert!(pricing.get("claude-opus-4-7").is_none());
+        assert!(pricing.get("claude-3-5-haiku-latest").is_none());
+        assert!(pricing.get("claude-opus-4-2025").is_none());
+
+        let pricing = pricing.with_model("claude-opus-4-5", ModelPricing::new(1.0, 2.0));
+        assert_eq!(pricing.get("claude-opus-4-5-20251101").unwrap().input, 1.0);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let usage = Usage {
+            input_tokens: Some(1_000_000),
+            output_tokens: Some(100_000),
+            cache_creation_input_tokens: Some(300_000),
+            cache_read_input_tokens: Some(2_000_000),
+            cache_creation: Some(CacheCreation {
+                ephemeral_5m_input_tokens: Some(100_000),
+                ephemeral_1h_input_tokens: Some(100_000),
+            }),
+            server_tool_use: Some(ServerToolUsage {
+                web_search_requests: Some(3),
+            }),
+            service_tier: Some("standard".to_string()),
+        };
+
+        let cost = PricingTable::default()
+            .estimate("claude-sonnet-4-20250514", &usage)
+            .unwrap();
+
+        assert_close(cost.input, 3.0);
+        assert_close(cost.output, 1.5);
+        // 200k at the 5 minute price, 100k at the 1 hour price
+        assert_close(cost.cache_write, 0.2 * 3.75 + 0.1 * 6.0);
+        assert_close(cost.cache_read, 2.0 * 0.3);
+        assert_close(cost.web_search, 0.03);
+        assert_close(cost.total(), 3.0 + 1.5 + 1.35 + 0.6 + 0.03);
+    }
+
+    #[test]
+    fn test_estimate_batch() {
+        let usage = Usage {
+            input_tokens: Some(1_000_000),
+            output_tokens: Some(1_000_000),
+            service_tier: Some("batch".to_string()),
+            ..Default::default()
+        };
+
+        let cost = PricingTable::default()
+            .estimate("claude-3-haiku-20240307", &usage)
+            .unwrap();
+
+        assert_close(cost.input, 0.125);
+        assert_close(cost.output, 0.625);
+    }
+}
@@ -0,0 +1,372 @@
+//! Rate limit headers and client-side throttling
+//!
+//! Every response of the API reports the rate limits of the organization in
+//! `anthropic-ratelimit-*` headers, parsed into [`RateLimitInfo`] and
+//! available from the metadata of the `*_with_meta` methods, see
+//! [`crate::response`].
+//!
+//! [`RateLimiter`] delays requests before they are sent so a client stays
+//! within its limits instead of running into 429s. It keeps a token bucket
+//! for requests and one for input tokens, refilled continuously, and lowers
+//! them to what the API reports as remaining.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::rate_limit::RateLimiter;
+//! let client = async_anthropic::Client::builder()
+//!     .rate_limiter(RateLimiter::new(50, 40_000))
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use http::HeaderMap;
+use serde_json::Value;
+
+use crate::{
+    errors::AnthropicError,
+    middleware::{Middleware, Next},
+    retry::retry_after,
+    transport::{HttpRequest, HttpResponse},
+};
+
+/// A single limit as reported by the API
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RateLimit {
+    /// The maximum allowed within the period
+    pub limit: Option<u64>,
+    /// What is left before being rate limited
+    pub remaining: Option<u64>,
+    /// When the limit is fully replenished, in RFC 3339 format
+    pub reset: Option<String>,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap, name: &str) -> Self {
+        let header = |suffix: &str| {
+            headers
+                .get(format!("anthropic-ratelimit-{name}-{suffix}"))
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+
+        Self {
+            limit: header("limit").and_then(|value| value.parse().ok()),
+            remaining: header("remaining").and_then(|value| value.parse().ok()),
+            reset: header("reset").map(str::to_string),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.limit.is_none() && self.remaining.is_none() && self.reset.is_none()
+    }
+}
+
+/// The rate limits reported in the headers of a response
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RateLimitInfo {
+    pub requests: RateLimit,
+    /// The most restrictive of the input and output token limits
+    pub tokens: RateLimit,
+    pub input_tokens: RateLimit,
+    pub output_tokens: RateLimit,
+    /// From the `retry-after-ms` or `retry-after` header
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimitInfo {
+    /// Parse the rate limit headers, `None` if there are none
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let info = Self {
+            requests: RateLimit::from_headers(headers, "requests"),
+            tokens: RateLimit::from_headers(headers, "tokens"),
+            input_tokens: RateLimit::from_headers(headers, "input-tokens"),
+            output_tokens: RateLimit::from_headers(headers, "output-tokens"),
+            retry_after: retry_after(headers),
+        };
+
+        let is_empty = info.requests.is_empty()
+            && info.tokens.is_empty()
+            && info.input_tokens.is_empty()
+            && info.output_tokens.is_empty()
+            && info.retry_after.is_none();
+
+        (!is_empty).then_some(info)
+    }
+}
+
+/// Throttles requests to stay within a number of requests and input tokens
+/// per minute
+///
+/// Input tokens of a message request are estimated at a token per four bytes
+/// of its body, leaving out base64 image and document data. Other requests
+/// only count against the request limit.
+///
+/// Add it to a client with `ClientBuilder::rate_limiter`; it is a
+/// [`Middleware`] and can be placed in the chain like any other.
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Bucket,
+    input_tokens: Bucket,
+}
+
+impl RateLimiter {
+    /// A limit of 0 disables that limit
+    pub fn new(requests_per_minute: u32, input_tokens_per_minute: u32) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                requests: Bucket::per_minute(requests_per_minute),
+                input_tokens: Bucket::per_minute(input_tokens_per_minute),
+            }),
+        }
+    }
+
+    /// Wait until the request fits within the limits, and take it from them
+    pub async fn acquire(&self, input_tokens: u32) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = Instant::now();
+                buckets.requests.refill(now);
+                buckets.input_tokens.refill(now);
+
+                let wait = buckets
+                    .requests
+                    .wait_for(1.0)
+                    .max(buckets.input_tokens.wait_for(f64::from(input_tokens)));
+
+                if wait.is_zero() {
+                    buckets.requests.take(1.0);
+                    buckets.input_tokens.take(f64::from(input_tokens));
+                    return;
+                }
+                wait
+            };
+
+            tracing::debug!("Rate limiter delaying request for {wait:?}");
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Lower the available capacity to what the API reports as remaining
+    pub fn update(&self, info: &RateLimitInfo) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+
+        if let Some(remaining) = info.requests.remaining {
+            buckets.requests.limit_to(remaining, now);
+        }
+        if let Some(remaining) = info.input_tokens.remaining.or(info.tokens.remaining) {
+            buckets.input_tokens.limit_to(remaining, now);
+        }
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn handle<'a>(
+        &'a self,
+        request: HttpRequest,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<HttpResponse, AnthropicError>> {
+        Box::pin(async move {
+            self.acquire(estimate_input_tokens(&request)).await;
+
+            let response = next.run(request).await?;
+            if let Some(info) = RateLimitInfo::from_headers(response.headers()) {
+                self.update(&info);
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+fn estimate_input_tokens(request: &HttpRequest) -> u32 {
+    if !request.uri().path().ends_with("/v1/messages") {
+        return 0;
+    }
+
+    let mut bytes = request.body().len();
+    if let Ok(body) = serde_json::from_slice::<Value>(request.body()) {
+        bytes = bytes.saturating_sub(base64_len(&body));
+    }
+    u32::try_from(bytes / 4).unwrap_or(u32::MAX)
+}
+
+/// The length of the base64 data of the image and document sources in `value`
+fn base64_len(value: &Value) -> usize {
+    match value {
+        Value::Object(object) if object.get("type").and_then(Value::as_str) == Some("base64") => {
+            object
+                .get("data")
+                .and_then(Value::as_str)
+                .map_or(0, str::len)
+        }
+        Value::Object(object) => object.values().map(base64_len).sum(),
+        Value::Array(values) => values.iter().map(base64_len).sum(),
+        _ => 0,
+    }
+}
+
+/// A token bucket, refilling its capacity once per minute
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    per_second: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn per_minute(capacity: u32) -> Self {
+        let capacity = f64::from(capacity);
+        Self {
+            capacity,
+            available: capacity,
+            per_second: capacity / 60.0,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.available =
+            (self.available + elapsed.as_secs_f64() * self.per_second).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// Time until `amount` is available, amounts over the capacity only wait
+    /// for a full bucket
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 || self.per_second <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(missing / self.per_second)
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+
+    fn limit_to(&mut self, remaining: u64, now: Instant) {
+        self.refill(now);
+        self.available = self.available.min(remaining as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimitInfo::from_headers(&headers), None);
+
+        for (name, value) in [
+            ("anthropic-ratelimit-requests-limit", "50"),
+            ("anthropic-ratelimit-requests-remaining", "49"),
+            ("anthropic-ratelimit-requests-reset", "2025-01-01T00:00:01Z"),
+            ("anthropic-ratelimit-input-tokens-remaining", "39000"),
+            ("retry-after", "3"),
+        ] {
+            headers.insert(name, value.parse().unwrap());
+        }
+
+        let info = RateLimitInfo::from_headers(&headers).unwrap();
+
+        assert_eq!(
+            info.requests,
+            RateLimit {
+                limit: Some(50),
+                remaining: Some(49),
+                reset: Some("2025-01-01T00:00:01Z".to_string()),
+            }
+        );
+        assert_eq!(info.input_tokens.remaining, Some(39000));
+        assert_eq!(info.output_tokens, RateLimit::default());
+        assert_eq!(info.retry_after, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_bucket() {
+        let start = Instant::now();
+        let mut bucket = Bucket::per_minute(60);
+        bucket.updated_at = start;
+
+        assert_eq!(bucket.wait_for(60.0), Duration::ZERO);
+        bucket.take(60.0);
+        assert_eq!(bucket.wait_for(2.0), Duration::from_secs(2));
+        // Requests larger than the bucket wait for a full bucket
+        assert_eq!(bucket.wait_for(600.0), Duration::from_secs(60));
+
+        bucket.refill(start + Duration::from_secs(30));
+        assert_eq!(bucket.available, 30.0);
+
+        bucket.limit_to(10, start + Duration::from_secs(30));
+        assert_eq!(bucket.available, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_zero_disables_limit() {
+        let limiter = RateLimiter::new(0, 0);
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            limiter.acquire(100_000).await;
+        }
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_estimate_leaves_out_base64_data() {
+        let request = |body: Value| {
+            http::Request::post("https://api.anthropic.com/v1/messages")
+                .body(Bytes::from(body.to_string()))
+                .unwrap()
+        };
+        let text = serde_json::json!({
+            "model": "claude",
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "a".repeat(400)}]}]
+        });
+        let image = serde_json::json!({
+            "model": "claude",
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "a".repeat(400)},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "A".repeat(40_000)}}
+            ]}]
+        });
+
+        let text_tokens = estimate_input_tokens(&request(text));
+        let image_tokens = estimate_input_tokens(&request(image));
+
+        assert!(text_tokens >= 100);
+        assert!(image_tokens < text_tokens + 50);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_capacity() {
+        // 100 requests and tokens per second
+        let limiter = RateLimiter::new(6000, 6000);
+        let start = Instant::now();
+
+        limiter.acquire(6000).await;
+        limiter.acquire(10).await;
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
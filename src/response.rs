@@ -0,0 +1,132 @@
+//! Responses along with the details of the HTTP response
+//!
+//! Every method of the API resources has a `*_with_meta` variant returning a
+//! [`Response`], which holds the deserialized data and the [`ResponseMeta`]
+//! of the call: status, headers, request id, rate limits and the size of the
+//! body.
+//!
+//! Include the request id when reporting issues to Anthropic.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::types::*;
+//! # async fn run() {
+//! let client = async_anthropic::Client::default();
+//! # let request = CreateMessagesRequestBuilder::default().build().unwrap();
+//! let response = client.messages().create_with_meta(request).await.unwrap();
+//!
+//! println!("{:?}: {:?}", response.meta.request_id, response.data.content);
+//! # }
+//! ```
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use http::{HeaderMap, StatusCode};
+use tokio_stream::StreamExt as _;
+
+use crate::{
+    rate_limit::RateLimitInfo,
+    transport::{HttpResponse, ResponseBody},
+};
+
+/// Header with the unique id the API assigns to every request
+pub(crate) const REQUEST_ID_HEADER: &str = "request-id";
+
+/// Deserialized data along with the metadata of the response
+///
+/// Derefs to the data.
+#[derive(Debug, Clone)]
+pub struct Response<T> {
+    pub data: T,
+    pub meta: ResponseMeta,
+}
+
+impl<T> Response<T> {
+    pub fn into_data(self) -> T {
+        self.data
+    }
+
+    pub fn into_parts(self) -> (T, ResponseMeta) {
+        (self.data, self.meta)
+    }
+
+    /// Map the data, keeping the metadata
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Response<U> {
+        Response {
+            data: f(self.data),
+            meta: self.meta,
+        }
+    }
+}
+
+impl<T> Deref for Response<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Response<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+/// Metadata of an HTTP response
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// From the `request-id` header
+    pub request_id: Option<String>,
+    /// From the `anthropic-ratelimit-*` headers
+    pub rate_limit: Option<RateLimitInfo>,
+    body_size: Arc<AtomicUsize>,
+}
+
+impl ResponseMeta {
+    /// Take the metadata of `response`, counting the bytes of its body as
+    /// they are read
+    pub(crate) fn from_response(response: HttpResponse) -> (Self, HttpResponse) {
+        let body_size = Arc::new(AtomicUsize::new(0));
+        let meta = Self {
+            status: response.status(),
+            headers: response.headers().clone(),
+            request_id: request_id(response.headers()),
+            rate_limit: RateLimitInfo::from_headers(response.headers()),
+            body_size: body_size.clone(),
+        };
+
+        let response = response.map(|body| {
+            Box::pin(body.map(move |chunk| {
+                if let Ok(chunk) = &chunk {
+                    body_size.fetch_add(chunk.len(), Ordering::Relaxed);
+                }
+                chunk
+            })) as ResponseBody
+        });
+
+        (meta, response)
+    }
+
+    /// Size of the raw body in bytes
+    ///
+    /// For streams, this is the size of the part of the body read so far.
+    pub fn body_size(&self) -> usize {
+        self.body_size.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
@@ -0,0 +1,636 @@
+//! Resume message streams that are cut off by transport failures
+//!
+//! When the connection drops after the message has started, the request is
+//! sent again with the content received so far prefilled as the last
+//! assistant turn. The continuation is spliced into the original stream: a
+//! text block that was cut off is continued under its original index, and
+//! later blocks are numbered after the blocks already emitted.
+//!
+//! The continuation gets the part of `max_tokens` that is left, estimated at
+//! a token per four bytes of the content emitted so far. When nothing is
+//! left, the message ends with the `max_tokens` stop reason instead.
+//!
+//! Every attempt is billed, so the usage reported at the end of the stream is
+//! the sum of all attempts.
+//!
+//! Thinking and tool use blocks can not be prefilled partially, a stream
+//! that is cut off inside one of them yields the error instead. Nor can a
+//! tool use be prefilled without its result: a stream cut off right after a
+//! tool use ends there with the `tool_use` stop reason, or yields the error
+//! for a server tool use.
+use tokio_stream::StreamExt as _;
+
+use crate::{
+    accumulator::{merge_usage, MessageAccumulator},
+    errors::AnthropicError,
+    messages::Messages,
+    types::{
+        ContentBlockDelta, CreateMessagesRequest, CreateMessagesResponseStream, Message,
+        MessageContent, MessageContentList, MessageDelta, MessageRole, MessagesStreamEvent,
+        StopReason, Usage,
+    },
+    Client,
+};
+
+/// Resume `stream`, the response to `request`, when it is cut off
+pub(crate) fn resumable_stream(
+    client: Client,
+    request: CreateMessagesRequest,
+    mut stream: CreateMessagesResponseStream,
+    max_resumes: usize,
+) -> CreateMessagesResponseStream {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut state = ResumeState::default();
+        let mut resumes = 0;
+
+        loop {
+            let mut error = None;
+
+            while let Some(event) = stream.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) if is_disconnect(&e) => {
+                        error = Some(e);
+                        break;
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+
+                for event in state.map(event) {
+                    if let Err(e) = state.accumulator.push(&event) {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                    if tx.send(Ok(event)).is_err() {
+                        // rx dropped
+                        return;
+                    }
+                }
+
+                if state.stopped {
+                    return;
+                }
+            }
+
+            let error = error.unwrap_or_else(|| {
+                AnthropicError::StreamTransport("stream ended before message_stop".to_string())
+            });
+
+            match state.resume(&request) {
+                Some(Resumption::Stop(events)) => {
+                    for event in events {
+                        if let Err(e) = state.accumulator.push(&event) {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                        let _ = tx.send(Ok(event));
+                    }
+                    return;
+                }
+                Some(Resumption::Continue(request)) if resumes < max_resumes => {
+                    resumes += 1;
+                    tracing::warn!(
+                        "Resuming interrupted stream ({resumes}/{max_resumes}): {error}"
+                    );
+                    stream = Messages::new(&client).stream(&request).await;
+                }
+                _ => {
+                    let _ = tx.send(Err(error));
+                    return;
+                }
+            }
+        }
+    });
+
+    Box::pin(tokio_stream::wrappers::UnboundedReceiverStream::new(rx))
+}
+
+/// Errors after which the stream can be resumed
+fn is_disconnect(error: &AnthropicError) -> bool {
+    match error {
+        #[cfg(feature = "reqwest")]
+        AnthropicError::Network(_) => true,
+        AnthropicError::Transport(_) => true,
+        _ => false,
+    }
+}
+
+/// How to go on after a stream is cut off
+#[derive(Debug)]
+enum Resumption {
+    /// Send the request continuing the message
+    Continue(Box<CreateMessagesRequest>),
+    /// The message can not go on, end it with these events
+    Stop(Vec<MessagesStreamEvent>),
+}
+
+/// Tracks the events emitted so far and how the events of a continuation map
+/// onto them
+#[derive(Debug, Default)]
+struct ResumeState {
+    /// The message as emitted
+    accumulator: MessageAccumulator,
+    started: bool,
+    stopped: bool,
+    /// Index of the block that is started but not stopped
+    open: Option<usize>,
+    /// Added to the block indices of the current continuation
+    offset: usize,
+    /// Text block that was cut off, to be continued by the first block of
+    /// the continuation
+    pending_merge: Option<usize>,
+    /// Whether trailing whitespace of the cut off text was not prefilled
+    trimmed_whitespace: bool,
+    /// Whether the next text delta continues the cut off text
+    strip_leading_whitespace: bool,
+    /// Usage of the interrupted attempts, `None` before the first resume
+    previous_usage: Option<Usage>,
+    /// Usage of the current attempt so far
+    attempt_usage: Option<Usage>,
+}
+
+impl ResumeState {
+    /// Map an event of the current attempt to the events to emit
+    fn map(&mut self, event: MessagesStreamEvent) -> Vec<MessagesStreamEvent> {
+        let mut events = Vec::new();
+
+        match event {
+            MessagesStreamEvent::MessageStart {
+                ref message,
+                ref usage,
+            } => {
+                self.attempt_usage = None;
+                merge_usage(&mut self.attempt_usage, message.usage.as_ref());
+                merge_usage(&mut self.attempt_usage, usage.as_ref());
+
+                if !self.started {
+                    self.started = true;
+                    events.push(event);
+                }
+            }
+            MessagesStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                if let Some(merged) = self.pending_merge.take() {
+                    if index == 0 && matches!(content_block, MessageContent::Text(_)) {
+                        self.strip_leading_whitespace = self.trimmed_whitespace;
+                        return events;
+                    }
+
+                    events.push(MessagesStreamEvent::ContentBlockStop { index: merged });
+                    self.offset = merged + 1;
+                }
+
+                let index = self.offset + index;
+                self.open = Some(index);
+                events.push(MessagesStreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                });
+            }
+            MessagesStreamEvent::ContentBlockDelta { index, mut delta } => {
+                if let ContentBlockDelta::TextDelta { text } = &mut delta {
+                    if self.strip_leading_whitespace {
+                        self.strip_leading_whitespace = false;
+                        *text = text.trim_start().to_string();
+                    }
+                    if text.is_empty() {
+                        return events;
+                    }
+                }
+
+                events.push(MessagesStreamEvent::ContentBlockDelta {
+                    index: self.offset + index,
+                    delta,
+                });
+            }
+            MessagesStreamEvent::ContentBlockStop { index } => {
+                self.open = None;
+                self.strip_leading_whitespace = false;
+                events.push(MessagesStreamEvent::ContentBlockStop {
+                    index: self.offset + index,
+                });
+            }
+            MessagesStreamEvent::MessageDelta { delta, mut usage } => {
+                self.close_pending_merge(&mut events);
+
+                merge_usage(&mut self.attempt_usage, usage.as_ref());
+                if let Some(previous) = &self.previous_usage {
+                    usage = Some(previous.clone() + self.attempt_usage.clone().unwrap_or_default());
+                }
+                events.push(MessagesStreamEvent::MessageDelta { delta, usage });
+            }
+            MessagesStreamEvent::MessageStop => {
+                self.close_pending_merge(&mut events);
+                self.stopped = true;
+                events.push(event);
+            }
+            MessagesStreamEvent::Ping | MessagesStreamEvent::Unknown(_) => events.push(event),
+        }
+
+        events
+    }
+
+    /// Stop the cut off text block if the continuation did not continue it
+    fn close_pending_merge(&mut self, events: &mut Vec<MessagesStreamEvent>) {
+        if let Some(merged) = self.pending_merge.take() {
+            self.open = None;
+            events.push(MessagesStreamEvent::ContentBlockStop { index: merged });
+        }
+    }
+
+    /// How to continue the message emitted so far, or `None` if it can not
+    /// be continued
+    fn resume(&mut self, request: &CreateMessagesRequest) -> Option<Resumption> {
+        if !self.started {
+            return None;
+        }
+
+        if let Some(usage) = self.attempt_usage.take() {
+            *self.previous_usage.get_or_insert_with(Usage::default) += usage;
+        }
+
+        let mut content = self.accumulator.response().content.clone();
+        self.trimmed_whitespace = false;
+        self.strip_leading_whitespace = false;
+
+        match self.open {
+            Some(open) => {
+                let Some(MessageContent::Text(text)) = content.get_mut(open) else {
+                    return None;
+                };
+
+                self.trimmed_whitespace = trim_end(&mut text.text);
+                if text.text.is_empty() {
+                    content.remove(open);
+                }
+
+                self.pending_merge = Some(open);
+                self.offset = open;
+            }
+            None => {
+                match content.last() {
+                    Some(MessageContent::ToolUse(_)) => {
+                        return Some(Resumption::Stop(self.stop_events(StopReason::ToolUse)));
+                    }
+                    Some(MessageContent::ServerToolUse(_)) => return None,
+                    _ => {}
+                }
+
+                self.pending_merge = None;
+                self.offset = content.len();
+
+                // The continuation of a text block that ended in whitespace
+                // starts with that whitespace again
+                if let Some(MessageContent::Text(text)) = content.last_mut() {
+                    self.trimmed_whitespace = trim_end(&mut text.text);
+                    self.strip_leading_whitespace = self.trimmed_whitespace;
+                    if text.text.is_empty() {
+                        content.pop();
+                    }
+                }
+            }
+        }
+
+        let budget = u32::try_from(request.max_tokens).unwrap_or_default();
+        let remaining = budget.saturating_sub(self.emitted_output_tokens());
+        if remaining == 0 {
+            return Some(Resumption::Stop(self.stop_events(StopReason::MaxTokens)));
+        }
+
+        let mut request = request.clone();
+        request.max_tokens = i32::try_from(remaining).unwrap_or(i32::MAX);
+        if content.is_empty() {
+            return Some(Resumption::Continue(Box::new(request)));
+        }
+
+        match request.messages.last_mut() {
+            Some(message) if message.role == MessageRole::Assistant => {
+                message.content.0.extend(content);
+            }
+            _ => request.messages.push(Message {
+                role: MessageRole::Assistant,
+                content: MessageContentList(content),
+            }),
+        }
+
+        Some(Resumption::Continue(Box::new(request)))
+    }
+
+    /// Output tokens of the content emitted so far, the larger of the usage
+    /// reported by the interrupted attempts and an estimate from its size
+    fn emitted_output_tokens(&self) -> u32 {
+        let bytes = self
+            .accumulator
+            .response()
+            .content
+            .iter()
+            .map(|content| match content {
+                MessageContent::Text(text) => text.text.len(),
+                MessageContent::Thinking(thinking) => thinking.thinking.len(),
+                MessageContent::ToolUse(tool_use) => tool_use.input.to_string().len(),
+                MessageContent::ServerToolUse(tool_use) => tool_use.input.to_string().len(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        let estimate = u32::try_from(bytes.div_ceil(4)).unwrap_or(u32::MAX);
+
+        let reported = self
+            .previous_usage
+            .as_ref()
+            .and_then(|usage| usage.output_tokens)
+            .unwrap_or_default();
+
+        estimate.max(reported)
+    }
+
+    /// Events ending the message with `stop_reason`
+    fn stop_events(&mut self, stop_reason: StopReason) -> Vec<MessagesStreamEvent> {
+        let mut events = Vec::new();
+        if let Some(open) = self.open.take() {
+            events.push(MessagesStreamEvent::ContentBlockStop { index: open });
+        }
+        events.push(MessagesStreamEvent::MessageDelta {
+            delta: MessageDelta {
+                stop_reason: Some(stop_reason),
+                stop_sequence: None,
+            },
+            usage: self.previous_usage.clone(),
+        });
+        events.push(MessagesStreamEvent::MessageStop);
+        self.stopped = true;
+
+        events
+    }
+}
+
+/// Trim trailing whitespace, which the api rejects at the end of a prefill,
+/// returning whether there was any
+fn trim_end(text: &mut String) -> bool {
+    let trimmed = text.trim_end().len();
+    let had_whitespace = trimmed < text.len();
+    text.truncate(trimmed);
+    had_whitespace
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::types::{CreateMessagesRequestBuilder, Text};
+
+    use super::*;
+
+    fn events(events: serde_json::Value) -> Vec<MessagesStreamEvent> {
+        serde_json::from_value(events).unwrap()
+    }
+
+    fn request() -> CreateMessagesRequest {
+        CreateMessagesRequestBuilder::default()
+            .model("claude")
+            .messages(vec!["Tell me a story".into()])
+            .build()
+            .unwrap()
+    }
+
+    fn continuation(
+        state: &mut ResumeState,
+        request: &CreateMessagesRequest,
+    ) -> CreateMessagesRequest {
+        match state.resume(request) {
+            Some(Resumption::Continue(request)) => *request,
+            other => panic!("expected a continuation, got {other:?}"),
+        }
+    }
+
+    fn apply(state: &mut ResumeState, events: Vec<MessagesStreamEvent>) {
+        for event in events {
+            for event in state.map(event) {
+                state.accumulator.push(&event).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_resume_continues_cut_off_text() {
+        let mut state = ResumeState::default();
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": [], "usage": {"input_tokens": 10, "output_tokens": 1}}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Once upon "}},
+            ])),
+        );
+
+        let resumed = continuation(&mut state, &request());
+        assert_eq!(resumed.messages.len(), 2);
+        assert_eq!(resumed.messages[1].role, MessageRole::Assistant);
+        assert_eq!(
+            resumed.messages[1].content.0,
+            vec![MessageContent::Text(Text::from("Once upon"))]
+        );
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_2", "model": "claude", "role": "assistant", "content": [], "usage": {"input_tokens": 15, "output_tokens": 1}}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " a time"}},
+                {"type": "content_block_stop", "index": 0},
+                {"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "draw", "input": {}}},
+                {"type": "content_block_stop", "index": 1},
+                {"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 5}},
+                {"type": "message_stop"}
+            ])),
+        );
+
+        let response = state.accumulator.finish();
+        assert!(state.stopped);
+        assert_eq!(response.id.as_deref(), Some("msg_1"));
+        assert_eq!(response.content.len(), 2);
+        assert_eq!(response.content[0], "Once upon a time".into());
+        assert_eq!(response.content[1].as_tool_use().unwrap().name, "draw");
+        // Both attempts are billed
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.input_tokens, Some(25));
+        assert_eq!(usage.output_tokens, Some(6));
+    }
+
+    #[test]
+    fn test_resume_after_completed_blocks() {
+        let mut state = ResumeState::default();
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me draw."}},
+                {"type": "content_block_stop", "index": 0},
+            ])),
+        );
+
+        let resumed = continuation(&mut state, &request());
+        assert_eq!(resumed.messages[1].content.0, vec!["Let me draw.".into()]);
+
+        let mapped = state.map(MessagesStreamEvent::ContentBlockStart {
+            index: 0,
+            content_block: MessageContent::Text("More".into()),
+        });
+        assert!(matches!(
+            mapped[..],
+            [MessagesStreamEvent::ContentBlockStart { index: 1, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_resume_trims_text_completed_before_the_cut() {
+        let mut state = ResumeState::default();
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Chapter one.\n\n"}},
+                {"type": "content_block_stop", "index": 0},
+            ])),
+        );
+
+        let resumed = continuation(&mut state, &request());
+        assert_eq!(resumed.messages[1].content.0, vec!["Chapter one.".into()]);
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_2", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "\n\nIt was dark."}},
+                {"type": "content_block_stop", "index": 0},
+                {"type": "message_delta", "delta": {"stop_reason": "end_turn"}},
+                {"type": "message_stop"}
+            ])),
+        );
+
+        let response = state.accumulator.finish();
+        assert_eq!(
+            response.content,
+            vec!["Chapter one.\n\n".into(), "It was dark.".into()]
+        );
+    }
+
+    #[test]
+    fn test_resume_within_max_tokens() {
+        let mut state = ResumeState::default();
+        let mut request = request();
+        request.max_tokens = 10;
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Once upon a time there "}},
+            ])),
+        );
+
+        // 23 bytes are about 6 tokens
+        assert_eq!(continuation(&mut state, &request).max_tokens, 4);
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_2", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " was a princess"}},
+            ])),
+        );
+
+        let Some(Resumption::Stop(events)) = state.resume(&request) else {
+            panic!("expected the message to stop");
+        };
+        apply(&mut state, events);
+
+        let response = state.accumulator.finish();
+        assert!(state.stopped);
+        assert_eq!(response.stop_reason, Some(StopReason::MaxTokens));
+        assert_eq!(
+            response.content,
+            vec!["Once upon a time there was a princess".into()]
+        );
+    }
+
+    #[test]
+    fn test_no_resume_inside_tool_use() {
+        let mut state = ResumeState::default();
+        assert!(state.resume(&request()).is_none());
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "draw", "input": {}}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"sh"}},
+            ])),
+        );
+
+        assert!(state.resume(&request()).is_none());
+    }
+
+    #[test]
+    fn test_no_resume_after_tool_use() {
+        let mut state = ResumeState::default();
+
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": [], "usage": {"input_tokens": 10, "output_tokens": 1}}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}},
+                {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me draw."}},
+                {"type": "content_block_stop", "index": 0},
+                {"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "draw", "input": {}}},
+                {"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"shape\": \"circle\"}"}},
+                {"type": "content_block_stop", "index": 1},
+            ])),
+        );
+
+        let Some(Resumption::Stop(stop)) = state.resume(&request()) else {
+            panic!("expected the message to stop");
+        };
+        for event in &stop {
+            state.accumulator.push(event).unwrap();
+        }
+
+        let response = state.accumulator.finish();
+        assert!(state.stopped);
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(response.content.len(), 2);
+        assert_eq!(
+            response.content[1].as_tool_use().unwrap().input,
+            json!({"shape": "circle"})
+        );
+        assert_eq!(response.usage.unwrap().input_tokens, Some(10));
+
+        // Server tools run on the server, the result can't be made up
+        let mut state = ResumeState::default();
+        apply(
+            &mut state,
+            events(json!([
+                {"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}},
+                {"type": "content_block_start", "index": 0, "content_block": {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {"query": "rust"}}},
+                {"type": "content_block_stop", "index": 0},
+            ])),
+        );
+        // `{"query":"rust"}` is 16 bytes
+        assert_eq!(state.emitted_output_tokens(), 4);
+        assert!(state.resume(&request()).is_none());
+    }
+}
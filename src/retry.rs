@@ -0,0 +1,246 @@
+//! Decide which failed requests are retried, and when
+//!
+//! Every failed attempt is passed to the [`RetryPolicy`] of the client as a
+//! [`RetryContext`]. The policy returns the delay before the next attempt, or
+//! `None` to give up and return the error.
+//!
+//! [`DefaultRetryPolicy`] retries rate limits, overloaded and server errors
+//! and network failures, waiting as long as the API asks for with the
+//! `retry-after-ms` and `retry-after` headers, and otherwise for the next
+//! delay of the client's backoff.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use async_anthropic::retry::DefaultRetryPolicy;
+//! let client = async_anthropic::Client::builder()
+//!     .retry_policy(
+//!         DefaultRetryPolicy::default()
+//!             .with_max_attempts(5)
+//!             .with_deadline(Duration::from_secs(60)),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::time::Duration;
+
+use http::{HeaderMap, StatusCode};
+
+use crate::errors::AnthropicError;
+
+/// Decides whether and when a failed request is retried
+pub trait RetryPolicy: std::fmt::Debug + Send + Sync {
+    /// The delay before the next attempt, or `None` to return the error
+    fn retry(&self, context: &RetryContext<'_>) -> Option<Duration>;
+}
+
+/// A failed attempt
+#[derive(Debug, Clone, Copy)]
+pub struct RetryContext<'a> {
+    /// The number of attempts made so far, starting at 1
+    pub attempt: usize,
+    /// Time since the first attempt started
+    pub elapsed: Duration,
+    pub error: &'a AnthropicError,
+    /// Status of the response, if there was one
+    pub status: Option<StatusCode>,
+    /// Delay requested by the `retry-after-ms` or `retry-after` header
+    pub retry_after: Option<Duration>,
+    /// Next delay of the client's backoff, `None` once it is exhausted
+    pub backoff: Option<Duration>,
+}
+
+/// Retries transient failures for as long as the client's backoff allows
+///
+/// Retried are:
+/// - 408, 409, 429 and 5xx statuses, including 529 (overloaded)
+/// - api errors of type `overloaded_error`, `api_error` and
+///   `rate_limit_error`
+/// - timeouts, connection and other network errors, and failures of custom
+///   transports
+#[derive(Debug, Clone, Default)]
+pub struct DefaultRetryPolicy {
+    max_attempts: Option<usize>,
+    deadline: Option<Duration>,
+}
+
+impl DefaultRetryPolicy {
+    /// Stop after this many attempts, the first one included
+    #[must_use]
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Do not retry if the next attempt would start later than `deadline`
+    /// after the first one
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// See [`AnthropicError::is_retryable`]
+    pub fn is_retryable(&self, context: &RetryContext<'_>) -> bool {
+        context.error.is_retryable_with_status(context.status)
+    }
+}
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn retry(&self, context: &RetryContext<'_>) -> Option<Duration> {
+        if !self.is_retryable(context) {
+            return None;
+        }
+
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| context.attempt >= max_attempts)
+        {
+            return None;
+        }
+
+        // The backoff limits the number of retries, even if the API tells us
+        // how long to wait
+        let delay = context
+            .backoff
+            .map(|backoff| context.retry_after.unwrap_or(backoff))?;
+
+        if self
+            .deadline
+            .is_some_and(|deadline| context.elapsed + delay > deadline)
+        {
+            return None;
+        }
+
+        Some(delay)
+    }
+}
+
+/// Parse the delay requested by the API, preferring the more precise
+/// `retry-after-ms`
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite() && *value >= 0.0)
+    };
+
+    header("retry-after-ms")
+        .map(|millis| Duration::from_secs_f64(millis / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+/// A failed attempt along with the response details the policy needs
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub error: AnthropicError,
+    pub status: Option<StatusCode>,
+    pub retry_after: Option<Duration>,
+}
+
+impl<E: Into<AnthropicError>> From<E> for Failure {
+    fn from(error: E) -> Self {
+        Self {
+            error: error.into(),
+            status: None,
+            retry_after: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ApiError;
+
+    use super::*;
+
+    fn context(error: &AnthropicError, status: Option<u16>) -> RetryContext<'_> {
+        RetryContext {
+            attempt: 1,
+            elapsed: Duration::ZERO,
+            error,
+            status: status.map(|status| StatusCode::from_u16(status).unwrap()),
+            retry_after: None,
+            backoff: Some(Duration::from_secs(1)),
+        }
+    }
+
+    #[test]
+    fn test_retryable_failures() {
+        let policy = DefaultRetryPolicy::default();
+        let api_error = |error_type: &str| {
+            AnthropicError::Api(ApiError {
+                error_type: error_type.to_string(),
+                message: None,
+                status: None,
+                request_id: None,
+            })
+        };
+
+        let overloaded = api_error("overloaded_error");
+        let invalid = api_error("invalid_request_error");
+        let cut_off = AnthropicError::StreamTransport("connection closed".to_string());
+        let bad_gateway = AnthropicError::Status {
+            status: StatusCode::BAD_GATEWAY,
+            body: "Bad Gateway".to_string(),
+            request_id: None,
+        };
+
+        assert!(policy.is_retryable(&context(&overloaded, None)));
+        assert!(policy.is_retryable(&context(&cut_off, Some(502))));
+        assert!(policy.is_retryable(&context(&bad_gateway, None)));
+        assert!(!policy.is_retryable(&context(&invalid, Some(400))));
+        assert!(!policy.is_retryable(&context(&cut_off, None)));
+    }
+
+    #[test]
+    fn test_retry_limits() {
+        let error = AnthropicError::RateLimit {
+            retry_after: None,
+            status: StatusCode::TOO_MANY_REQUESTS,
+            request_id: None,
+        };
+        let policy = DefaultRetryPolicy::default()
+            .with_max_attempts(3)
+            .with_deadline(Duration::from_secs(10));
+
+        let first = RetryContext {
+            retry_after: Some(Duration::from_secs(2)),
+            ..context(&error, Some(429))
+        };
+        assert_eq!(policy.retry(&first), Some(Duration::from_secs(2)));
+
+        let exhausted = RetryContext {
+            backoff: None,
+            ..first
+        };
+        assert_eq!(policy.retry(&exhausted), None);
+
+        let too_late = RetryContext {
+            elapsed: Duration::from_secs(9),
+            ..first
+        };
+        assert_eq!(policy.retry(&too_late), None);
+
+        let too_many = RetryContext {
+            attempt: 3,
+            ..first
+        };
+        assert_eq!(policy.retry(&too_many), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+
+        headers.insert("retry-after-ms", "1500.5".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs_f64(1.5005)));
+    }
+}
@@ -0,0 +1,268 @@
+//! Derive tool input schemas and structured output formats from Rust types
+//!
+//! Requires the `schemars` feature.
+//!
+//! Generated schemas are normalized to the subset the API accepts:
+//! subschemas are inlined, the root must be an object and `format`s the API
+//! does not know are dropped. In strict mode every object gets
+//! `additionalProperties: false` and unsupported constraints are removed.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::types::*;
+//! #[derive(schemars::JsonSchema, serde::Deserialize)]
+//! struct WeatherInput {
+//!     /// The city and state, e.g. San Francisco, CA
+//!     location: String,
+//! }
+//!
+//! let tool = CustomToolBuilder::default()
+//!     .name("get_weather")
+//!     .input_schema(ToolInputSchema::from_type::<WeatherInput>(false).unwrap())
+//!     .build()
+//!     .unwrap();
+//! ```
+use schemars::{generate::SchemaSettings, JsonSchema};
+use serde_json::{Map, Value};
+
+use crate::{
+    errors::AnthropicError,
+    types::{JsonOutputFormat, ToolInputSchema},
+};
+
+/// String formats supported by the API
+const SUPPORTED_FORMATS: &[&str] = &[
+    "date-time",
+    "time",
+    "date",
+    "duration",
+    "email",
+    "hostname",
+    "uri",
+    "ipv4",
+    "ipv6",
+    "uuid",
+];
+
+/// Keywords that are rejected in strict mode
+const STRICT_UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "minLength",
+    "maxLength",
+];
+
+impl ToolInputSchema {
+    /// Derive the input schema of a tool from `T`
+    ///
+    /// With `strict`, objects do not allow additional properties. Set
+    /// `CustomTool::strict` accordingly.
+    pub fn from_type<T: JsonSchema>(strict: bool) -> Result<Self, AnthropicError> {
+        let mut schema = schema_for::<T>(strict)?;
+
+        let properties = match schema.remove("properties") {
+            Some(Value::Object(properties)) => properties,
+            _ => Map::new(),
+        };
+        let required = match schema.remove("required") {
+            Some(Value::Array(required)) => required
+                .into_iter()
+                .filter_map(|name| name.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(ToolInputSchema {
+            properties,
+            required,
+            additional_properties: strict.then_some(false),
+            ..Default::default()
+        })
+    }
+}
+
+impl JsonOutputFormat {
+    /// Derive a strict json schema output format from `T`
+    pub fn from_type<T: JsonSchema>() -> Result<Self, AnthropicError> {
+        Ok(JsonOutputFormat::JsonSchema {
+            schema: schema_for::<T>(true)?,
+        })
+    }
+}
+
+fn schema_for<T: JsonSchema>(strict: bool) -> Result<Map<String, Value>, AnthropicError> {
+    let generator = SchemaSettings::draft2020_12()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+
+    let Value::Object(mut schema) = generator.into_root_schema_for::<T>().to_value() else {
+        return Err(AnthropicError::InvalidSchema(format!(
+            "schema of {} is not an object",
+            T::schema_name()
+        )));
+    };
+
+    // Only recursive types keep references when subschemas are inlined
+    if schema.contains_key("$defs") || schema.values().any(contains_ref) {
+        return Err(AnthropicError::InvalidSchema(format!(
+            "recursive type {} is not supported",
+            T::schema_name()
+        )));
+    }
+
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        return Err(AnthropicError::InvalidSchema(format!(
+            "root of the schema of {} must be an object",
+            T::schema_name()
+        )));
+    }
+
+    schema.remove("title");
+    normalize(&mut schema, strict);
+
+    Ok(schema)
+}
+
+fn normalize(schema: &mut Map<String, Value>, strict: bool) {
+    let unsupported_format = schema
+        .get("format")
+        .and_then(Value::as_str)
+        .is_some_and(|format| !SUPPORTED_FORMATS.contains(&format));
+    if unsupported_format {
+        schema.remove("format");
+    }
+
+    if strict {
+        for keyword in STRICT_UNSUPPORTED_KEYWORDS {
+            schema.remove(*keyword);
+        }
+
+        if is_object_schema(schema) {
+            schema.insert("additionalProperties".to_string(), Value::Bool(false));
+        }
+    }
+
+    for (keyword, value) in schema.iter_mut() {
+        match (keyword.as_str(), value) {
+            // Maps of property names to schemas
+            ("properties" | "patternProperties", Value::Object(properties)) => {
+                for property in properties.values_mut() {
+                    if let Value::Object(property) = property {
+                        normalize(property, strict);
+                    }
+                }
+            }
+            // Lists of schemas
+            ("anyOf" | "oneOf" | "allOf" | "prefixItems", Value::Array(schemas)) => {
+                for schema in schemas {
+                    if let Value::Object(schema) = schema {
+                        normalize(schema, strict);
+                    }
+                }
+            }
+            // Single schemas
+            ("items" | "additionalProperties" | "not", Value::Object(schema)) => {
+                normalize(schema, strict);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn contains_ref(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => map.contains_key("$ref") || map.values().any(contains_ref),
+        Value::Array(values) => values.iter().any(contains_ref),
+        _ => false,
+    }
+}
+
+fn is_object_schema(schema: &Map<String, Value>) -> bool {
+    match schema.get("type") {
+        Some(Value::String(kind)) => kind == "object",
+        Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind == "object"),
+        _ => schema.contains_key("properties"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::JsonSchema;
+    use serde_json::json;
+
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Location {
+        city: String,
+        country: Option<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct WeatherInput {
+        /// Where to look up the weather
+        location: Location,
+        days: u8,
+        #[schemars(extend("format" = "currency"))]
+        unit: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Node {
+        children: Vec<Node>,
+    }
+
+    #[test]
+    fn test_tool_input_schema_from_type() {
+        let schema = ToolInputSchema::from_type::<WeatherInput>(false).unwrap();
+
+        assert_eq!(schema.required, vec!["location", "days", "unit"]);
+        assert_eq!(schema.additional_properties, None);
+        assert_eq!(
+            schema.properties["location"]["description"],
+            "Where to look up the weather"
+        );
+        assert_eq!(
+            schema.properties["location"]["properties"]["city"],
+            json!({"type": "string"})
+        );
+        assert!(schema.properties["days"].get("minimum").is_some());
+        assert!(schema.properties["unit"].get("format").is_none());
+    }
+
+    #[test]
+    fn test_strict_schema_disallows_additional_properties() {
+        let JsonOutputFormat::JsonSchema { schema } =
+            JsonOutputFormat::from_type::<WeatherInput>().unwrap();
+
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(
+            schema["properties"]["location"]["additionalProperties"],
+            false
+        );
+        assert!(schema["properties"]["days"].get("minimum").is_none());
+        assert!(schema.get("$schema").is_none());
+    }
+
+    #[test]
+    fn test_unsupported_schemas_are_rejected() {
+        assert!(matches!(
+            ToolInputSchema::from_type::<String>(false),
+            Err(AnthropicError::InvalidSchema(_))
+        ));
+        assert!(matches!(
+            ToolInputSchema::from_type::<Node>(false),
+            Err(AnthropicError::InvalidSchema(_))
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+//! Decoding of server-sent events from a response body
+
+/// A single server-sent event
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct SseEvent {
+    /// Defaults to `message` if the event has no name
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+}
+
+/// Incrementally splits a byte stream into events
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>,
+    id: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk of the body, returning the events it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n' || *b == b'\r') {
+            // A trailing \r may be the first half of \r\n
+            if self.buffer[end] == b'\r' && end + 1 == self.buffer.len() {
+                break;
+            }
+
+            let line = self.buffer.drain(..=end).collect::<Vec<_>>();
+            if line.ends_with(b"\r") && self.buffer.first() == Some(&b'\n') {
+                self.buffer.remove(0);
+            }
+
+            let line = String::from_utf8_lossy(&line[..line.len() - 1]);
+            if let Some(event) = self.line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// End the body, dropping an event that was not terminated by a blank
+    /// line, as the body was likely cut off inside it
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let buffer = std::mem::take(&mut self.buffer);
+
+        // A trailing \r ends a blank line, it is not the first half of \r\n
+        if buffer == b"\r" {
+            return self.dispatch();
+        }
+
+        self.event = None;
+        self.data = None;
+        None
+    }
+
+    fn line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        match field {
+            // Comment
+            "" => {}
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                let data = self.data.get_or_insert_with(String::new);
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value);
+            }
+            "id" => self.id = Some(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let data = self.data.take()?;
+
+        Some(SseEvent {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+            id: self.id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+
+        let mut events = decoder.push(b": ping\r\nevent: message_start\r");
+        events.extend(decoder.push(b"\ndata: {\"a\":\r\n"));
+        events.extend(decoder.push(b"data: 1}\r\n\r\nevent: message_stop\ndata: {}\n\r"));
+        events.extend(decoder.finish());
+
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: "message_start".to_string(),
+                    data: "{\"a\":\n1}".to_string(),
+                    id: None,
+                },
+                SseEvent {
+                    event: "message_stop".to_string(),
+                    data: "{}".to_string(),
+                    id: None,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_cut_off_at_the_end_are_dropped() {
+        let mut decoder = SseDecoder::new();
+
+        let events = decoder.push(b"event: ping\ndata: {}\n\nevent: content_block_delta\n");
+        assert_eq!(events.len(), 1);
+        assert!(decoder
+            .push(b"data: {\"type\": \"content_block_delta\", \"ind")
+            .is_empty());
+        assert_eq!(decoder.finish(), None);
+
+        // Also when the cut is right after a complete line
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"event: message_stop\ndata: {}\n").is_empty());
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_events_without_data_are_skipped() {
+        let mut decoder = SseDecoder::new();
+
+        let events = decoder.push(b"event: ping\n\ndata: hello\n\n");
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "message");
+        assert_eq!(events[0].data, "hello");
+    }
+}
@@ -0,0 +1,198 @@
+//! Deserialize enums tagged by `type` that keep values of unknown types
+//!
+//! The derived deserializer of a tagged enum buffers the whole value before
+//! looking at the tag, so errors lose their position in the payload, and an
+//! untagged fallback variant also swallows values of known types that do not
+//! deserialize. Instead, [`deserialize`] reads the tag first and streams the
+//! rest of the value into the variant, which is described by an externally
+//! tagged `remote` definition of the enum. Only values with a tag the
+//! definition does not know end up in the fallback variant.
+use std::marker::PhantomData;
+
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, MapDeserializer},
+        DeserializeSeed, EnumAccess, IgnoredAny, IntoDeserializer as _, MapAccess, VariantAccess,
+        Visitor,
+    },
+    Deserializer,
+};
+use serde_json::{Map, Value};
+
+/// An enum tagged by `type` with a fallback for unknown types
+pub(crate) trait Tagged: Sized {
+    /// Deserialize a known variant with the externally tagged definition of
+    /// the enum, see [`deserialize`]
+    fn deserialize_variant<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+
+    /// The fallback for a value of an unknown type
+    fn unknown(value: Value) -> Self;
+}
+
+/// Implement `Deserialize` for an enum tagged by `type` from its externally
+/// tagged `remote` definition, keeping values of unknown types in its
+/// `Unknown` variant
+macro_rules! deserialize_tagged {
+    ($name:ident, $definition:ident) => {
+        impl $crate::tagged::Tagged for $name {
+            fn deserialize_variant<'de, D: serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                $definition::deserialize(deserializer)
+            }
+
+            fn unknown(value: serde_json::Value) -> Self {
+                $name::Unknown(value)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                $crate::tagged::deserialize(deserializer)
+            }
+        }
+    };
+}
+
+pub(crate) fn deserialize<'de, T: Tagged, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer.deserialize_map(TaggedVisitor(PhantomData))
+}
+
+struct TaggedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Tagged> Visitor<'de> for TaggedVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("an object with a `type`")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let Some(key) = map.next_key::<String>()? else {
+            return Err(de::Error::missing_field("type"));
+        };
+        if key == "type" {
+            let tag = map.next_value::<String>()?;
+            return variant(tag, &mut map);
+        }
+
+        // The tag is usually first, otherwise the value has to be buffered
+        let mut object = Map::new();
+        object.insert(key, map.next_value()?);
+        while let Some((key, value)) = map.next_entry()? {
+            object.insert(key, value);
+        }
+
+        let tag = match object.remove("type") {
+            Some(Value::String(tag)) => tag,
+            Some(_) => return Err(de::Error::custom("expected `type` to be a string")),
+            None => return Err(de::Error::missing_field("type")),
+        };
+        let mut rest = MapDeserializer::<_, serde_json::Error>::new(object.into_iter());
+        variant(tag, &mut rest).map_err(de::Error::custom)
+    }
+}
+
+/// Deserialize the variant tagged `tag` from the rest of `map`
+fn variant<'de, T: Tagged, A: MapAccess<'de>>(tag: String, map: &mut A) -> Result<T, A::Error> {
+    let mut unknown = false;
+    let result = T::deserialize_variant(VariantDeserializer {
+        tag: &tag,
+        map: &mut *map,
+        unknown: &mut unknown,
+    });
+    if !unknown {
+        return result;
+    }
+
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::String(tag));
+    while let Some((key, value)) = map.next_entry()? {
+        object.insert(key, value);
+    }
+    Ok(T::unknown(Value::Object(object)))
+}
+
+/// Presents a tag and the rest of a map as an externally tagged enum
+struct VariantDeserializer<'a, A> {
+    tag: &'a str,
+    map: &'a mut A,
+    /// Set if the definition does not know the tag
+    unknown: &'a mut bool,
+}
+
+impl<'de, A: MapAccess<'de>> Deserializer<'de> for VariantDeserializer<'_, A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, A::Error> {
+        Err(de::Error::custom("expected an enum definition"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        visitor.visit_enum(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a, A: MapAccess<'de>> EnumAccess<'de> for VariantDeserializer<'a, A> {
+    type Error = A::Error;
+    type Variant = VariantMap<'a, A>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantMap<'a, A>), A::Error> {
+        match seed.deserialize(self.tag.into_deserializer()) {
+            Ok(variant) => Ok((variant, VariantMap(self.map))),
+            Err(e) => {
+                *self.unknown = true;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// The rest of the map, holding the content of the variant
+struct VariantMap<'a, A>(&'a mut A);
+
+impl<'de, A: MapAccess<'de>> VariantAccess<'de> for VariantMap<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        while self.0.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        seed.deserialize(MapAccessDeserializer::new(self.0))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        Err(de::Error::custom("tagged enums can't have tuple variants"))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        visitor.visit_map(self.0)
+    }
+}
@@ -0,0 +1,335 @@
+//! Typed tools and dispatching of [`ToolUse`] blocks
+//!
+//! Implement [`TypedTool`] for each tool, register them in a [`ToolRegistry`]
+//! and use the registry both to describe the tools in a
+//! [`crate::types::CreateMessagesRequest`] and to answer the tool uses in the
+//! response.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{tools::*, types::*};
+//! # use serde::Deserialize;
+//! #[derive(Deserialize)]
+//! struct WeatherInput {
+//!     location: String,
+//! }
+//!
+//! struct GetWeather;
+//!
+//! impl TypedTool for GetWeather {
+//!     type Input = WeatherInput;
+//!     type Error = std::convert::Infallible;
+//!
+//!     fn name(&self) -> &str {
+//!         "get_weather"
+//!     }
+//!
+//!     fn input_schema(&self) -> ToolInputSchema {
+//!         ToolInputSchemaBuilder::default()
+//!             .properties(serde_json::Map::from_iter([(
+//!                 "location".to_string(),
+//!                 serde_json::json!({"type": "string"}),
+//!             )]))
+//!             .required(vec!["location".to_string()])
+//!             .build()
+//!             .unwrap()
+//!     }
+//!
+//!     async fn call(&self, input: WeatherInput) -> Result<String, Self::Error> {
+//!         Ok(format!("Pretty warm in {}", input.location))
+//!     }
+//! }
+//!
+//! # async fn run(response: CreateMessagesResponse) {
+//! let mut registry = ToolRegistry::new();
+//! registry.register(GetWeather);
+//!
+//! for message in response.messages() {
+//!     let results = registry.call_all(&message.tool_uses()).await;
+//! }
+//! # }
+//! ```
+use std::{fmt::Display, future::Future, pin::Pin, sync::Arc};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::types::{CustomTool, Tool, ToolInputSchema, ToolResult, ToolUse};
+
+/// A tool with a typed input that can be called by the model
+pub trait TypedTool: Send + Sync + 'static {
+    /// The input the model provides, deserialized from [`ToolUse::input`]
+    type Input: DeserializeOwned + Send;
+
+    /// Error returned from [`TypedTool::call`], reported back to the model
+    type Error: Display;
+
+    fn name(&self) -> &str;
+
+    fn description(&self) -> Option<&str> {
+        None
+    }
+
+    /// JSON schema of [`TypedTool::Input`]
+    ///
+    /// With the `schemars` feature it can be derived with
+    /// `ToolInputSchema::from_type`.
+    fn input_schema(&self) -> ToolInputSchema;
+
+    /// The definition sent to the API
+    fn definition(&self) -> CustomTool {
+        CustomTool {
+            name: self.name().to_string(),
+            input_schema: self.input_schema(),
+            description: self.description().map(str::to_string),
+            cache_control: None,
+            strict: None,
+        }
+    }
+
+    /// Run the tool, the returned text is sent back as the tool result
+    fn call(&self, input: Self::Input) -> impl Future<Output = Result<String, Self::Error>> + Send;
+}
+
+type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
+
+/// Object safe version of [`TypedTool`], taking the raw json input
+trait DynTool: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn definition(&self) -> CustomTool;
+
+    fn call_json(&self, input: Value) -> ToolFuture<'_>;
+}
+
+impl<T: TypedTool> DynTool for T {
+    fn name(&self) -> &str {
+        TypedTool::name(self)
+    }
+
+    fn definition(&self) -> CustomTool {
+        TypedTool::definition(self)
+    }
+
+    fn call_json(&self, input: Value) -> ToolFuture<'_> {
+        Box::pin(async move {
+            let input = serde_json::from_value::<T::Input>(input)
+                .map_err(|e| format!("invalid input: {e}"))?;
+
+            self.call(input).await.map_err(|e| e.to_string())
+        })
+    }
+}
+
+/// A set of tools, dispatching tool uses to them by name
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn DynTool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tool, replacing any tool with the same name
+    pub fn register(&mut self, tool: impl TypedTool) -> &mut Self {
+        self.tools.retain(|existing| existing.name() != tool.name());
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    /// Add a tool, replacing any tool with the same name
+    #[must_use]
+    pub fn with_tool(mut self, tool: impl TypedTool) -> Self {
+        self.register(tool);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    /// The tool definitions, to be used in `CreateMessagesRequest::tools`
+    pub fn tools(&self) -> Vec<Tool> {
+        self.tools
+            .iter()
+            .map(|tool| Tool::Custom(tool.definition()))
+            .collect()
+    }
+
+    /// Call the tool requested by `tool_use`
+    ///
+    /// Unknown tools, invalid input and errors returned by the tool result in
+    /// a [`ToolResult`] with `is_error` set, so the model can correct itself.
+    #[tracing::instrument(skip_all, fields(tool = %tool_use.name))]
+    pub async fn call(&self, tool_use: &ToolUse) -> ToolResult {
+        let result = match self.tools.iter().find(|tool| tool.name() == tool_use.name) {
+            Some(tool) => tool.call_json(tool_use.input.clone()).await,
+            None => Err(format!("unknown tool: {}", tool_use.name)),
+        };
+
+        if let Err(error) = &result {
+            tracing::warn!("Tool call failed: {error}");
+        }
+
+        let is_error = result.is_err();
+        ToolResult {
+            tool_use_id: tool_use.id.clone(),
+            content: result.unwrap_or_else(|error| error).into(),
+            is_error,
+            cache_control: None,
+        }
+    }
+
+    /// Call all tools in order, returning a result for each tool use
+    pub async fn call_all(&self, tool_uses: &[ToolUse]) -> Vec<ToolResult> {
+        let mut results = Vec::with_capacity(tool_uses.len());
+        for tool_use in tool_uses {
+            results.push(self.call(tool_use).await);
+        }
+        results
+    }
+
+    /// Call all tools concurrently, returning the results in the same order as
+    /// the tool uses
+    pub async fn call_all_concurrently(&self, tool_uses: &[ToolUse]) -> Vec<ToolResult> {
+        futures::future::join_all(tool_uses.iter().map(|tool_use| self.call(tool_use))).await
+    }
+}
+
+impl std::fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.tools.iter().map(|tool| tool.name()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct AddInput {
+        a: i64,
+        b: i64,
+    }
+
+    struct Add;
+
+    impl TypedTool for Add {
+        type Input = AddInput;
+        type Error = String;
+
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        fn description(&self) -> Option<&str> {
+            Some("Adds two numbers")
+        }
+
+        fn input_schema(&self) -> ToolInputSchema {
+            ToolInputSchema::default()
+        }
+
+        async fn call(&self, input: AddInput) -> Result<String, String> {
+            input
+                .a
+                .checked_add(input.b)
+                .map(|sum| sum.to_string())
+                .ok_or_else(|| "overflow".to_string())
+        }
+    }
+
+    struct Clear;
+
+    impl TypedTool for Clear {
+        type Input = Value;
+        type Error = String;
+
+        fn name(&self) -> &str {
+            "clear"
+        }
+
+        fn input_schema(&self) -> ToolInputSchema {
+            ToolInputSchema::default()
+        }
+
+        async fn call(&self, _input: Value) -> Result<String, String> {
+            Ok(String::new())
+        }
+    }
+
+    fn tool_use(name: &str, input: Value) -> ToolUse {
+        ToolUse {
+            id: "toolu_1".to_string(),
+            name: name.to_string(),
+            input,
+            cache_control: None,
+        }
+    }
+
+    #[test]
+    fn test_tools_definitions() {
+        let registry = ToolRegistry::new().with_tool(Add).with_tool(Add);
+
+        let Tool::Custom(tool) = &registry.tools()[0] else {
+            panic!("expected custom tool");
+        };
+
+        assert_eq!(registry.len(), 1);
+        assert_eq!(tool.name, "add");
+        assert_eq!(tool.description.as_deref(), Some("Adds two numbers"));
+    }
+
+    #[tokio::test]
+    async fn test_call_tool() {
+        let registry = ToolRegistry::new().with_tool(Add);
+
+        let result = registry
+            .call(&tool_use("add", json!({"a": 1, "b": 2})))
+            .await;
+
+        assert_eq!(result.tool_use_id, "toolu_1");
+        assert_eq!(result.content.text(), "3");
+        assert!(!result.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_call_tool_without_output() {
+        let registry = ToolRegistry::new().with_tool(Clear);
+
+        let result = registry.call(&tool_use("clear", json!({}))).await;
+
+        assert!(result.content.is_empty());
+        assert!(!result.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_call_errors_are_reported() {
+        let registry = ToolRegistry::new().with_tool(Add);
+
+        let results = registry
+            .call_all(&[
+                tool_use("add", json!({"a": "one"})),
+                tool_use("add", json!({"a": i64::MAX, "b": 1})),
+                tool_use("subtract", json!({})),
+            ])
+            .await;
+
+        assert!(results.iter().all(|result| result.is_error));
+        assert!(results[0].content.text().starts_with("invalid input"));
+        assert_eq!(results[1].content.text(), "overflow");
+        assert_eq!(results[2].content.text(), "unknown tool: subtract");
+    }
+}
@@ -0,0 +1,111 @@
+//! The HTTP layer used by [`crate::Client`]
+//!
+//! All requests go through an [`HttpTransport`]: a request with a complete
+//! body goes in, a response with a streaming body comes out. Status codes,
+//! retries and parsing are handled by the client, so a transport only has to
+//! move bytes.
+//!
+//! [`ReqwestTransport`] is used by default and is available with the `rustls`
+//! or `native-tls` feature. A custom transport is set with
+//! `ClientBuilder::transport`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use async_anthropic::{errors::AnthropicError, transport::*};
+//! # use futures::future::BoxFuture;
+//! #[derive(Debug)]
+//! struct Logging<T>(T);
+//!
+//! impl<T: HttpTransport> HttpTransport for Logging<T> {
+//!     fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, AnthropicError>> {
+//!         println!("{} {}", request.method(), request.uri());
+//!         self.0.send(request)
+//!     }
+//! }
+//!
+//! let client = async_anthropic::Client::builder()
+//!     .transport(Logging(ReqwestTransport::default()))
+//!     .build()
+//!     .unwrap();
+//! ```
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use tokio_stream::{Stream, StreamExt as _};
+
+use crate::errors::AnthropicError;
+
+/// A request with its complete body
+pub type HttpRequest = http::Request<Bytes>;
+
+/// A response with a streaming body
+pub type HttpResponse = http::Response<ResponseBody>;
+
+/// The body of an [`HttpResponse`], as a stream of chunks
+pub type ResponseBody = Pin<Box<dyn Stream<Item = Result<Bytes, AnthropicError>> + Send>>;
+
+/// Sends requests to the API
+///
+/// Any response must be returned as is, including error statuses. Failures
+/// to get a response at all are best reported as
+/// [`AnthropicError::Transport`].
+pub trait HttpTransport: std::fmt::Debug + Send + Sync {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, AnthropicError>>;
+}
+
+/// Read a response body to the end
+pub(crate) async fn collect_body(mut body: ResponseBody) -> Result<Vec<u8>, AnthropicError> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+    Ok(bytes)
+}
+
+/// The default transport, backed by a [`reqwest::Client`]
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Client> for ReqwestTransport {
+    fn from(client: reqwest::Client) -> Self {
+        Self::new(client)
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpTransport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, AnthropicError>> {
+        Box::pin(async move {
+            let request = reqwest::Request::try_from(request.map(reqwest::Body::from))?;
+            let response = self.client.execute(request).await?;
+
+            let mut builder = http::Response::builder()
+                .status(response.status())
+                .version(response.version());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = response.headers().clone();
+            }
+
+            let body: ResponseBody = Box::pin(
+                response
+                    .bytes_stream()
+                    .map(|chunk| chunk.map_err(AnthropicError::Network)),
+            );
+
+            Ok(builder.body(body)?)
+        })
+    }
+}
@@ -1,21 +1,129 @@
 use std::{
     collections::BTreeMap,
     num::NonZeroU32,
-    ops::{Deref, DerefMut},
+    ops::{Add, AddAssign, Deref, DerefMut},
+    path::Path,
     pin::Pin,
 };
 
+use base64::{prelude::BASE64_STANDARD, Engine as _};
 use derive_builder::Builder;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Map, Value};
 use tokio_stream::Stream;
 
-use crate::{errors::AnthropicError, messages};
+use crate::{
+    errors::{AnthropicError, ApiError, ApiErrorEnvelope},
+    messages,
+};
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+/// Token usage of a request
+///
+/// Usage of multiple requests can be summed with `+` and `+=`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct Usage {
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    /// Input tokens written to the cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Input tokens read from the cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
+    /// Cache writes broken down by TTL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation: Option<CacheCreation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_tool_use: Option<ServerToolUsage>,
+    /// The tier the request was served with, e.g. `standard` or `priority`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct CacheCreation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ephemeral_5m_input_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ephemeral_1h_input_tokens: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct ServerToolUsage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_search_requests: Option<u32>,
+}
+
+/// Sums two counts, staying `None` only if both are and saturating at
+/// `u32::MAX`
+fn add_count(total: Option<u32>, count: Option<u32>) -> Option<u32> {
+    match (total, count) {
+        (None, None) => None,
+        (total, count) => Some(
+            total
+                .unwrap_or_default()
+                .saturating_add(count.unwrap_or_default()),
+        ),
+    }
+}
+
+fn add_optional<T: AddAssign<T> + Default>(total: &mut Option<T>, value: Option<T>) {
+    if let Some(value) = value {
+        *total.get_or_insert_with(T::default) += value;
+    }
+}
+
+impl AddAssign for CacheCreation {
+    fn add_assign(&mut self, rhs: Self) {
+        self.ephemeral_5m_input_tokens = add_count(
+            self.ephemeral_5m_input_tokens,
+            rhs.ephemeral_5m_input_tokens,
+        );
+        self.ephemeral_1h_input_tokens = add_count(
+            self.ephemeral_1h_input_tokens,
+            rhs.ephemeral_1h_input_tokens,
+        );
+    }
+}
+
+impl AddAssign for ServerToolUsage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.web_search_requests = add_count(self.web_search_requests, rhs.web_search_requests);
+    }
+}
+
+impl AddAssign for Usage {
+    /// Sums all counts, the service tier of `rhs` wins if set
+    fn add_assign(&mut self, rhs: Self) {
+        self.input_tokens = add_count(self.input_tokens, rhs.input_tokens);
+        self.output_tokens = add_count(self.output_tokens, rhs.output_tokens);
+        self.cache_creation_input_tokens = add_count(
+            self.cache_creation_input_tokens,
+            rhs.cache_creation_input_tokens,
+        );
+        self.cache_read_input_tokens =
+            add_count(self.cache_read_input_tokens, rhs.cache_read_input_tokens);
+        add_optional(&mut self.cache_creation, rhs.cache_creation);
+        add_optional(&mut self.server_tool_use, rhs.server_tool_use);
+        if rhs.service_tier.is_some() {
+            self.service_tier = rhs.service_tier;
+        }
+    }
+}
+
+impl AddAssign<&Usage> for Usage {
+    fn add_assign(&mut self, rhs: &Usage) {
+        *self += rhs.clone();
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -211,6 +319,60 @@ pub struct CreateMessagesRequest {
     pub cache_control: Option<CacheControl>,
 }
 
+impl CreateMessagesRequest {
+    /// All documents of the messages, in the order citations index them
+    pub fn documents(&self) -> impl Iterator<Item = &Document> {
+        self.messages
+            .iter()
+            .flat_map(|message| message.content.iter())
+            .filter_map(|content| match content {
+                MessageContent::Document(document) => Some(document),
+                _ => None,
+            })
+    }
+}
+
+/// Request body for the count tokens endpoint
+///
+/// Usually created from a [`CreateMessagesRequest`], dropping the fields that
+/// only affect generation such as `max_tokens` and `stream`.
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(setter(into, strip_option))]
+pub struct CountMessageTokensRequest {
+    pub messages: Vec<Message>,
+    pub model: String,
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<System>,
+    #[builder(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ExtendedThinking>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub tool_choice: Option<ToolChoice>,
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+}
+
+impl From<CreateMessagesRequest> for CountMessageTokensRequest {
+    fn from(request: CreateMessagesRequest) -> Self {
+        CountMessageTokensRequest {
+            messages: request.messages,
+            model: request.model,
+            system: request.system,
+            thinking: request.thinking,
+            tool_choice: request.tool_choice,
+            tools: request.tools,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CountMessageTokensResponse {
+    pub input_tokens: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct OutputConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -520,7 +682,7 @@ pub enum SystemContent {
     Text(Text),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[derive(Debug, Clone, Serialize, Deserialize, Builder, Default)]
 #[builder(setter(into, strip_option))]
 pub struct CreateMessagesResponse {
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -530,13 +692,89 @@ pub struct CreateMessagesResponse {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub stop_reason: Option<String>,
+    pub stop_reason: Option<StopReason>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stop_sequence: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub usage: Option<Usage>,
 }
 
+/// Why the model stopped generating
+///
+/// Unknown reasons are kept in [`StopReason::Other`], so new reasons the API
+/// adds never break deserialization.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum StopReason {
+    /// The model reached a natural stopping point
+    EndTurn,
+    /// The response reached `max_tokens`
+    MaxTokens,
+    /// The model generated one of the `stop_sequences`
+    StopSequence,
+    /// The model wants to use one or more tools
+    ToolUse,
+    /// A long running turn was paused, send the response back as is to
+    /// continue
+    PauseTurn,
+    /// The model declined to respond
+    Refusal,
+    /// The response reached the context window of the model
+    ModelContextWindowExceeded,
+    Other(String),
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            StopReason::EndTurn => "end_turn",
+            StopReason::MaxTokens => "max_tokens",
+            StopReason::StopSequence => "stop_sequence",
+            StopReason::ToolUse => "tool_use",
+            StopReason::PauseTurn => "pause_turn",
+            StopReason::Refusal => "refusal",
+            StopReason::ModelContextWindowExceeded => "model_context_window_exceeded",
+            StopReason::Other(other) => other,
+        }
+    }
+}
+
+impl From<String> for StopReason {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "end_turn" => StopReason::EndTurn,
+            "max_tokens" => StopReason::MaxTokens,
+            "stop_sequence" => StopReason::StopSequence,
+            "tool_use" => StopReason::ToolUse,
+            "pause_turn" => StopReason::PauseTurn,
+            "refusal" => StopReason::Refusal,
+            "model_context_window_exceeded" => StopReason::ModelContextWindowExceeded,
+            _ => StopReason::Other(s),
+        }
+    }
+}
+
+impl From<&str> for StopReason {
+    fn from(s: &str) -> Self {
+        s.to_string().into()
+    }
+}
+
+impl From<StopReason> for String {
+    fn from(stop_reason: StopReason) -> Self {
+        match stop_reason {
+            StopReason::Other(other) => other,
+            stop_reason => stop_reason.as_str().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl CreateMessagesResponse {
     /// Returns the content as Messages so they are more easily reusable
     pub fn messages(&self) -> Vec<Message> {
@@ -550,7 +788,34 @@ impl CreateMessagesResponse {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// A response parsed into the type requested as its output format
+#[derive(Debug, Clone)]
+pub struct StructuredResponse<T> {
+    pub output: T,
+    pub response: CreateMessagesResponse,
+}
+
+impl<T: serde::de::DeserializeOwned> StructuredResponse<T> {
+    /// Parse the text of a response into `T`
+    ///
+    /// Fails with [`AnthropicError::StructuredOutput`], containing the raw
+    /// text, if it does not deserialize into `T`.
+    pub fn from_response(response: CreateMessagesResponse) -> Result<Self, AnthropicError> {
+        let raw = response
+            .content
+            .iter()
+            .filter_map(MessageContent::as_text)
+            .map(|text| text.text.as_str())
+            .collect::<String>();
+
+        match serde_json::from_str(&raw) {
+            Ok(output) => Ok(StructuredResponse { output, response }),
+            Err(source) => Err(AnthropicError::StructuredOutput { raw, source }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageContent {
     ToolUse(ToolUse),
@@ -558,6 +823,12 @@ pub enum MessageContent {
     Text(Text),
     Thinking(Thinking),
     Document(Document),
+    Image(Image),
+
+    /// Use of a tool the API runs itself, like web search and code execution
+    ServerToolUse(ServerToolUse),
+    WebSearchToolResult(WebSearchToolResult),
+    CodeExecutionToolResult(CodeExecutionToolResult),
 
     /// See Anthropic's docs for more information:
     ///
@@ -573,8 +844,31 @@ pub enum MessageContent {
     RedactedThinking {
         data: String,
     },
+
+    /// A block of a type this version does not know, kept as is so it can be
+    /// sent back unchanged
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// The known variants of [`MessageContent`], see [`crate::tagged`]
+#[derive(Deserialize)]
+#[serde(remote = "MessageContent", rename_all = "snake_case")]
+enum MessageContentDef {
+    ToolUse(ToolUse),
+    ToolResult(ToolResult),
+    Text(Text),
+    Thinking(Thinking),
+    Document(Document),
+    Image(Image),
+    ServerToolUse(ServerToolUse),
+    WebSearchToolResult(WebSearchToolResult),
+    CodeExecutionToolResult(CodeExecutionToolResult),
+    RedactedThinking { data: String },
 }
 
+deserialize_tagged!(MessageContent, MessageContentDef);
+
 impl MessageContent {
     pub fn as_tool_use(&self) -> Option<&ToolUse> {
         if let MessageContent::ToolUse(tool_use) = self {
@@ -611,6 +905,13 @@ pub struct ToolUse {
     pub cache_control: Option<CacheControl>,
 }
 
+impl ToolUse {
+    /// Deserialize the input into the type the tool expects
+    pub fn input_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(&self.input)
+    }
+}
+
 impl From<ToolUse> for MessageContent {
     fn from(tool_use: ToolUse) -> Self {
         MessageContent::ToolUse(tool_use)
@@ -627,8 +928,8 @@ impl From<ToolUse> for MessageContentList {
 #[builder(setter(into, strip_option), default)]
 pub struct ToolResult {
     pub tool_use_id: String,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "ToolResultContent::is_empty")]
+    pub content: ToolResultContent,
     pub is_error: bool,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cache_control: Option<CacheControl>,
@@ -646,12 +947,182 @@ impl From<ToolResult> for MessageContentList {
     }
 }
 
+/// The content of a tool result
+///
+/// Deserializes from both a string and a list of blocks, and converts from a
+/// string or a single block. An empty string or `null` is empty content.
+#[derive(Debug, Clone, Serialize, PartialEq, Default)]
+pub struct ToolResultContent(pub Vec<ToolResultBlock>);
+
+impl ToolResultContent {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The text blocks of the content, concatenated
+    pub fn text(&self) -> String {
+        self.0
+            .iter()
+            .filter_map(|block| match block {
+                ToolResultBlock::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Deref for ToolResultContent {
+    type Target = Vec<ToolResultBlock>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ToolResultContent {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolResultContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ContentVisitor;
+
+        impl<'de> de::Visitor<'de> for ContentVisitor {
+            type Value = ToolResultContent;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a string or a list of blocks")
+            }
+
+            fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+                Ok(text.into())
+            }
+
+            fn visit_string<E: de::Error>(self, text: String) -> Result<Self::Value, E> {
+                Ok(text.into())
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(ToolResultContent::default())
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(ToolResultContent::default())
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(ToolResultContent)
+            }
+        }
+
+        deserializer.deserialize_any(ContentVisitor)
+    }
+}
+
+impl From<&str> for ToolResultContent {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl From<String> for ToolResultContent {
+    /// An empty string is empty content, as text blocks can't be empty
+    fn from(text: String) -> Self {
+        if text.is_empty() {
+            ToolResultContent::default()
+        } else {
+            Text::from(text).into()
+        }
+    }
+}
+
+impl From<Vec<ToolResultBlock>> for ToolResultContent {
+    fn from(blocks: Vec<ToolResultBlock>) -> Self {
+        ToolResultContent(blocks)
+    }
+}
+
+impl<B: Into<ToolResultBlock>> From<B> for ToolResultContent {
+    fn from(block: B) -> Self {
+        ToolResultContent(vec![block.into()])
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolResultBlock {
+    Text(Text),
+    Image(Image),
+    Document(Document),
+    SearchResult(SearchResult),
+
+    /// A block of a type this version does not know
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// The known variants of [`ToolResultBlock`], see [`crate::tagged`]
+#[derive(Deserialize)]
+#[serde(remote = "ToolResultBlock", rename_all = "snake_case")]
+enum ToolResultBlockDef {
+    Text(Text),
+    Image(Image),
+    Document(Document),
+    SearchResult(SearchResult),
+}
+
+deserialize_tagged!(ToolResultBlock, ToolResultBlockDef);
+
+impl From<Text> for ToolResultBlock {
+    fn from(text: Text) -> Self {
+        ToolResultBlock::Text(text)
+    }
+}
+
+impl From<Image> for ToolResultBlock {
+    fn from(image: Image) -> Self {
+        ToolResultBlock::Image(image)
+    }
+}
+
+impl From<Document> for ToolResultBlock {
+    fn from(document: Document) -> Self {
+        ToolResultBlock::Document(document)
+    }
+}
+
+impl From<SearchResult> for ToolResultBlock {
+    fn from(search_result: SearchResult) -> Self {
+        ToolResultBlock::SearchResult(search_result)
+    }
+}
+
+/// A result of a search tool, which the model can cite like a document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+#[builder(setter(into, strip_option), default)]
+pub struct SearchResult {
+    /// Where the result came from, e.g. a url
+    pub source: String,
+    pub title: String,
+    /// Only text blocks are accepted
+    pub content: Vec<ContentBlockSourceContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<CitationsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
 #[builder(setter(into, strip_option), default)]
 pub struct Text {
     pub text: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cache_control: Option<CacheControl>,
+    /// The sources of the text, for documents with citations enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<Vec<Citation>>,
 }
 
 impl<S: AsRef<str>> From<S> for Text {
@@ -659,6 +1130,197 @@ impl<S: AsRef<str>> From<S> for Text {
         Text {
             text: s.as_ref().to_string(),
             cache_control: None,
+            citations: None,
+        }
+    }
+}
+
+/// A reference from generated text to the part of a source it is based on
+///
+/// Resolve citations of documents to the cited part of the document with
+/// [`Citation::resolve`].
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Citation {
+    /// Characters of a plain text document
+    CharLocation(CharLocation),
+    /// Pages of a PDF document
+    PageLocation(PageLocation),
+    /// Blocks of a custom content document
+    ContentBlockLocation(ContentBlockLocation),
+    WebSearchResultLocation(WebSearchResultLocation),
+    SearchResultLocation(SearchResultLocation),
+
+    /// A citation of a type this version does not know
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// The known variants of [`Citation`], see [`crate::tagged`]
+#[derive(Deserialize)]
+#[serde(remote = "Citation", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum CitationDef {
+    CharLocation(CharLocation),
+    PageLocation(PageLocation),
+    ContentBlockLocation(ContentBlockLocation),
+    WebSearchResultLocation(WebSearchResultLocation),
+    SearchResultLocation(SearchResultLocation),
+}
+
+deserialize_tagged!(Citation, CitationDef);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CharLocation {
+    pub cited_text: String,
+    pub document_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_title: Option<String>,
+    /// 0-indexed, in characters
+    pub start_char_index: usize,
+    /// Exclusive
+    pub end_char_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PageLocation {
+    pub cited_text: String,
+    pub document_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_title: Option<String>,
+    /// 1-indexed
+    pub start_page_number: usize,
+    /// Exclusive
+    pub end_page_number: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContentBlockLocation {
+    pub cited_text: String,
+    pub document_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_title: Option<String>,
+    /// 0-indexed
+    pub start_block_index: usize,
+    /// Exclusive
+    pub end_block_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WebSearchResultLocation {
+    pub cited_text: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Send it back unchanged to keep the citation in later turns
+    pub encrypted_index: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SearchResultLocation {
+    pub cited_text: String,
+    pub search_result_index: usize,
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// 0-indexed
+    pub start_block_index: usize,
+    /// Exclusive
+    pub end_block_index: usize,
+}
+
+/// The part of a [`Document`] a citation refers to
+#[derive(Debug, Clone, PartialEq)]
+pub enum CitedSource<'a> {
+    /// Characters of a plain text document
+    Text(&'a str),
+    /// Blocks of a custom content document
+    Blocks(&'a [ContentBlockSourceContent]),
+    /// Pages of a PDF document, 1-indexed and exclusive at the end
+    Pages(std::ops::Range<usize>),
+}
+
+impl Citation {
+    /// The text as quoted by the API
+    pub fn cited_text(&self) -> Option<&str> {
+        match self {
+            Citation::CharLocation(location) => Some(&location.cited_text),
+            Citation::PageLocation(location) => Some(&location.cited_text),
+            Citation::ContentBlockLocation(location) => Some(&location.cited_text),
+            Citation::WebSearchResultLocation(location) => Some(&location.cited_text),
+            Citation::SearchResultLocation(location) => Some(&location.cited_text),
+            Citation::Unknown(citation) => citation.get("cited_text").and_then(Value::as_str),
+        }
+    }
+
+    /// Index of the cited document among all documents of the request
+    pub fn document_index(&self) -> Option<usize> {
+        match self {
+            Citation::CharLocation(location) => Some(location.document_index),
+            Citation::PageLocation(location) => Some(location.document_index),
+            Citation::ContentBlockLocation(location) => Some(location.document_index),
+            _ => None,
+        }
+    }
+
+    /// The part of the document in `request` this citation refers to
+    ///
+    /// `None` if the citation is not of a document, or it does not match the
+    /// documents of the request.
+    pub fn resolve<'a>(&self, request: &'a CreateMessagesRequest) -> Option<CitedSource<'a>> {
+        let document = request.documents().nth(self.document_index()?)?;
+        document.resolve(self)
+    }
+}
+
+impl Document {
+    /// The part of this document `citation` refers to, ignoring the document
+    /// index of the citation
+    pub fn resolve(&self, citation: &Citation) -> Option<CitedSource<'_>> {
+        match (citation, &self.source) {
+            (Citation::CharLocation(location), DocumentSource::Text { data, .. }) => {
+                let byte_index = |char_index| {
+                    data.char_indices()
+                        .map(|(index, _)| index)
+                        .chain(std::iter::once(data.len()))
+                        .nth(char_index)
+                };
+                let start = byte_index(location.start_char_index)?;
+                let end = byte_index(location.end_char_index)?;
+                data.get(start..end).map(CitedSource::Text)
+            }
+            (
+                Citation::PageLocation(location),
+                DocumentSource::Base64 { .. }
+                | DocumentSource::Url { .. }
+                | DocumentSource::File { .. },
+            ) => Some(CitedSource::Pages(
+                location.start_page_number..location.end_page_number,
+            )),
+            (
+                Citation::ContentBlockLocation(location),
+                DocumentSource::Content {
+                    content: DocumentSourceContent::Blocks(blocks),
+                },
+            ) => blocks
+                .get(location.start_block_index..location.end_block_index)
+                .map(CitedSource::Blocks),
+            // A string is a single text block
+            (
+                Citation::ContentBlockLocation(location),
+                DocumentSource::Content {
+                    content: DocumentSourceContent::String(text),
+                },
+            ) if location.start_block_index == 0 && location.end_block_index == 1 => {
+                Some(CitedSource::Text(text))
+            }
+            _ => None,
         }
     }
 }
@@ -718,11 +1380,116 @@ impl Thinking {
     }
 }
 
-impl<S: AsRef<str>> From<S> for Thinking {
-    fn from(s: S) -> Self {
-        Thinking {
-            thinking: s.as_ref().to_string(),
-            signature: None,
+/// Use of a server tool, the result follows in the same response
+///
+/// Like [`ToolUse`], the input is streamed as `input_json_delta`s.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+#[builder(setter(into, strip_option), default)]
+pub struct ServerToolUse {
+    pub id: String,
+    /// `web_search` or `code_execution`
+    pub name: String,
+    pub input: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebSearchToolResult {
+    /// Id of the [`ServerToolUse`] this is the result of
+    pub tool_use_id: String,
+    pub content: WebSearchToolResultContent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum WebSearchToolResultContent {
+    Results(Vec<WebSearchResult>),
+    Error(WebSearchToolResultError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename = "web_search_result")]
+pub struct WebSearchResult {
+    pub url: String,
+    pub title: String,
+    /// The page content, only readable by the API. Send it back unchanged to
+    /// keep the result in later turns.
+    pub encrypted_content: String,
+    /// When the page was last updated, e.g. `April 30, 2025`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_age: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename = "web_search_tool_result_error")]
+pub struct WebSearchToolResultError {
+    /// E.g. `max_uses_exceeded`, `too_many_requests` or `query_too_long`
+    pub error_code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CodeExecutionToolResult {
+    /// Id of the [`ServerToolUse`] this is the result of
+    pub tool_use_id: String,
+    pub content: CodeExecutionToolResultContent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CodeExecutionToolResultContent {
+    CodeExecutionResult(CodeExecutionResult),
+    CodeExecutionToolResultError {
+        /// E.g. `unavailable`, `code_execution_exceeded` or
+        /// `container_expired`
+        error_code: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct CodeExecutionResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub return_code: i32,
+    /// Files created by the code
+    #[serde(default)]
+    pub content: Vec<CodeExecutionOutput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename = "code_execution_output")]
+pub struct CodeExecutionOutput {
+    /// Download with the files api
+    pub file_id: String,
+}
+
+impl From<ServerToolUse> for MessageContent {
+    fn from(server_tool_use: ServerToolUse) -> Self {
+        MessageContent::ServerToolUse(server_tool_use)
+    }
+}
+
+impl From<WebSearchToolResult> for MessageContent {
+    fn from(result: WebSearchToolResult) -> Self {
+        MessageContent::WebSearchToolResult(result)
+    }
+}
+
+impl From<CodeExecutionToolResult> for MessageContent {
+    fn from(result: CodeExecutionToolResult) -> Self {
+        MessageContent::CodeExecutionToolResult(result)
+    }
+}
+
+impl<S: AsRef<str>> From<S> for Thinking {
+    fn from(s: S) -> Self {
+        Thinking {
+            thinking: s.as_ref().to_string(),
+            signature: None,
         }
     }
 }
@@ -763,6 +1530,10 @@ pub enum DocumentSource {
     Url {
         url: String,
     },
+    /// A file uploaded through the files api, see [`crate::files::Files`]
+    File {
+        file_id: String,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
@@ -813,6 +1584,10 @@ pub enum ImageSource {
     Url {
         url: String,
     },
+    /// A file uploaded through the files api, see [`crate::files::Files`]
+    File {
+        file_id: String,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -827,6 +1602,100 @@ pub enum ImageMediaType {
     Webp,
 }
 
+impl ImageMediaType {
+    /// Detect the media type of an image from its leading magic bytes
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        match data {
+            [0xFF, 0xD8, 0xFF, ..] => Some(ImageMediaType::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageMediaType::Png),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageMediaType::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+                Some(ImageMediaType::Webp)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageMediaType::Jpeg => "image/jpeg",
+            ImageMediaType::Png => "image/png",
+            ImageMediaType::Gif => "image/gif",
+            ImageMediaType::Webp => "image/webp",
+        }
+    }
+}
+
+/// An image in a user message or tool result
+///
+/// With the `image` feature, large images can be downscaled before they are
+/// sent, see [`crate::images`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Image {
+    pub source: ImageSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+impl Image {
+    /// Base64 encode an image, detecting the media type from its contents
+    ///
+    /// Fails if the image is not a jpeg, png, gif or webp.
+    pub fn from_bytes(data: impl AsRef<[u8]>) -> Result<Self, AnthropicError> {
+        let data = data.as_ref();
+        let media_type = ImageMediaType::sniff(data).ok_or_else(|| {
+            AnthropicError::InvalidImage("expected a jpeg, png, gif or webp image".to_string())
+        })?;
+
+        Ok(Image::from(ImageSource::Base64 {
+            data: BASE64_STANDARD.encode(data),
+            media_type,
+        }))
+    }
+
+    /// Read an image from disk, see [`Image::from_bytes`]
+    ///
+    /// Files that are not a supported image fail with
+    /// [`std::io::ErrorKind::InvalidData`].
+    pub async fn from_path(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let data = tokio::fs::read(path).await?;
+        Image::from_bytes(data)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn from_url(url: impl Into<String>) -> Self {
+        Image::from(ImageSource::Url { url: url.into() })
+    }
+
+    /// An image uploaded through the files api, see [`crate::files::Files`]
+    pub fn from_file_id(file_id: impl Into<String>) -> Self {
+        Image::from(ImageSource::File {
+            file_id: file_id.into(),
+        })
+    }
+}
+
+impl From<ImageSource> for Image {
+    fn from(source: ImageSource) -> Self {
+        Image {
+            source,
+            cache_control: None,
+        }
+    }
+}
+
+impl From<Image> for MessageContent {
+    fn from(image: Image) -> Self {
+        MessageContent::Image(image)
+    }
+}
+
+impl From<Image> for MessageContentList {
+    fn from(image: Image) -> Self {
+        MessageContentList(vec![image.into()])
+    }
+}
+
 impl From<Document> for MessageContent {
     fn from(document: Document) -> Self {
         MessageContent::Document(document)
@@ -853,10 +1722,7 @@ impl From<Thinking> for MessageContentList {
 
 impl<S: AsRef<str>> From<S> for MessageContent {
     fn from(s: S) -> Self {
-        MessageContent::Text(Text {
-            text: s.as_ref().to_string(),
-            cache_control: None,
-        })
+        MessageContent::Text(Text::from(s))
     }
 }
 
@@ -927,24 +1793,53 @@ impl Serialize for ToolChoice {
         }
     }
 }
-#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum ContentBlockDelta {
+    TextDelta {
+        text: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    CitationsDelta {
+        citation: Citation,
+    },
+
+    /// A delta of a type this version does not know
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// The known variants of [`ContentBlockDelta`], see [`crate::tagged`]
+#[derive(Deserialize)]
+#[serde(remote = "ContentBlockDelta", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum ContentBlockDeltaDef {
     TextDelta { text: String },
     ThinkingDelta { thinking: String },
     SignatureDelta { signature: String },
     InputJsonDelta { partial_json: String },
+    CitationsDelta { citation: Citation },
 }
 
+deserialize_tagged!(ContentBlockDelta, ContentBlockDeltaDef);
+
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct MessageDelta {
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub stop_reason: Option<String>,
+    pub stop_reason: Option<StopReason>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stop_sequence: Option<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Debug, PartialEq)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum MessagesStreamEvent {
     MessageStart {
@@ -970,7 +1865,42 @@ pub enum MessagesStreamEvent {
     },
     MessageStop,
     Ping,
+
+    /// An event of a type this version does not know
+    #[serde(untagged)]
+    Unknown(Value),
 }
+
+/// The known variants of [`MessagesStreamEvent`], see [`crate::tagged`]
+#[derive(Deserialize)]
+#[serde(remote = "MessagesStreamEvent", rename_all = "snake_case")]
+enum MessagesStreamEventDef {
+    MessageStart {
+        message: MessageStart,
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: MessageContent,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: ContentBlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    Ping,
+}
+
+deserialize_tagged!(MessagesStreamEvent, MessagesStreamEventDef);
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct MessageStart {
     pub id: String,
@@ -978,7 +1908,7 @@ pub struct MessageStart {
     pub role: String,
     pub content: Vec<MessageContent>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub stop_reason: Option<String>,
+    pub stop_reason: Option<StopReason>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stop_sequence: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -1089,6 +2019,200 @@ pub struct Model {
 
 pub type GetModelResponse = Model;
 
+/// Cursor parameters for paginated list endpoints
+#[derive(Clone, Debug, Default, PartialEq, Builder)]
+#[builder(setter(into, strip_option), default)]
+pub struct PaginationParams {
+    /// Return the page of results immediately before this object
+    pub before_id: Option<String>,
+    /// Return the page of results immediately after this object
+    pub after_id: Option<String>,
+    /// Number of items to return per page
+    pub limit: Option<u32>,
+}
+
+impl PaginationParams {
+    /// Renders the parameters as a query string, including the leading `?`
+    pub(crate) fn to_query(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(before_id) = &self.before_id {
+            query.append_pair("before_id", before_id);
+        }
+        if let Some(after_id) = &self.after_id {
+            query.append_pair("after_id", after_id);
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+
+        let query = query.finish();
+        if query.is_empty() {
+            query
+        } else {
+            format!("?{query}")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(setter(into, strip_option))]
+pub struct CreateMessageBatchRequest {
+    pub requests: Vec<MessageBatchRequest>,
+}
+
+impl From<Vec<MessageBatchRequest>> for CreateMessageBatchRequest {
+    fn from(requests: Vec<MessageBatchRequest>) -> Self {
+        CreateMessageBatchRequest { requests }
+    }
+}
+
+/// A single messages request in a batch, identified by a `custom_id` that is
+/// unique within the batch
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(setter(into, strip_option))]
+pub struct MessageBatchRequest {
+    pub custom_id: String,
+    pub params: CreateMessagesRequest,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MessageBatch {
+    pub id: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub processing_status: MessageBatchProcessingStatus,
+    pub request_counts: MessageBatchRequestCounts,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancel_initiated_at: Option<String>,
+    /// Only available once processing has ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results_url: Option<String>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageBatchProcessingStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct MessageBatchRequestCounts {
+    #[serde(default)]
+    pub processing: u32,
+    #[serde(default)]
+    pub succeeded: u32,
+    #[serde(default)]
+    pub errored: u32,
+    #[serde(default)]
+    pub canceled: u32,
+    #[serde(default)]
+    pub expired: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ListMessageBatchesResponse {
+    #[serde(default)]
+    pub data: Vec<MessageBatch>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_id: Option<String>,
+    pub has_more: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_id: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeleteMessageBatchResponse {
+    pub id: String,
+}
+
+/// A single line of a batch results file
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MessageBatchIndividualResponse {
+    pub custom_id: String,
+    pub result: MessageBatchResult,
+}
+
+impl MessageBatchIndividualResponse {
+    /// See [`MessageBatchResult::into_result`]
+    pub fn into_result(self) -> Result<CreateMessagesResponse, ApiError> {
+        self.result.into_result()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageBatchResult {
+    Succeeded { message: CreateMessagesResponse },
+    Errored { error: ApiErrorEnvelope },
+    Canceled,
+    Expired,
+}
+
+impl MessageBatchResult {
+    /// Converts the result into the response or error `Messages::create`
+    /// would have returned
+    ///
+    /// Canceled and expired requests become an [`ApiError`] with an
+    /// `error_type` of `"canceled"` and `"expired"` respectively.
+    pub fn into_result(self) -> Result<CreateMessagesResponse, ApiError> {
+        match self {
+            MessageBatchResult::Succeeded { message } => Ok(message),
+            MessageBatchResult::Errored { error } => Err(error.into_error(None)),
+            MessageBatchResult::Canceled => Err(ApiError {
+                error_type: "canceled".to_string(),
+                message: Some("request was canceled before it was processed".to_string()),
+                status: None,
+                request_id: None,
+            }),
+            MessageBatchResult::Expired => Err(ApiError {
+                error_type: "expired".to_string(),
+                message: Some("batch expired before the request was processed".to_string()),
+                status: None,
+                request_id: None,
+            }),
+        }
+    }
+}
+
+pub type MessageBatchResultsStream =
+    Pin<Box<dyn Stream<Item = Result<MessageBatchIndividualResponse, AnthropicError>> + Send>>;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FileMetadata {
+    pub id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+    /// Only files created by tools such as code execution can be downloaded
+    #[serde(default)]
+    pub downloadable: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ListFilesResponse {
+    #[serde(default)]
+    pub data: Vec<FileMetadata>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_id: Option<String>,
+    pub has_more: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_id: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeleteFileResponse {
+    pub id: String,
+}
+
 macro_rules! named_unit_variant {
     ($variant:tt) => {
         named_unit_variant!($variant, stringify!($variant));
@@ -1147,6 +2271,151 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_pagination_query() {
+        assert_eq!(PaginationParams::default().to_query(), "");
+
+        let params = PaginationParamsBuilder::default()
+            .after_id("file_a&b=c d")
+            .limit(10u32)
+            .build()
+            .unwrap();
+        assert_eq!(params.to_query(), "?after_id=file_a%26b%3Dc+d&limit=10");
+    }
+
+    #[test]
+    fn test_tool_result_content() {
+        let result: ToolResult = serde_json::from_value(json!({
+            "type": "tool_result",
+            "tool_use_id": "toolu_1",
+            "content": "It is sunny",
+            "is_error": false
+        }))
+        .unwrap();
+        assert_eq!(result.content, ToolResultContent::from("It is sunny"));
+
+        let result: ToolResult = serde_json::from_value(json!({
+            "tool_use_id": "toolu_2",
+            "content": [
+                {"type": "text", "text": "Found 1 result"},
+                {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}},
+                {
+                    "type": "search_result",
+                    "source": "https://example.com",
+                    "title": "Example",
+                    "content": [{"type": "text", "text": "An example"}],
+                    "citations": {"enabled": true}
+                },
+                {"type": "hologram"}
+            ],
+            "is_error": false
+        }))
+        .unwrap();
+        assert_eq!(result.content.text(), "Found 1 result");
+        assert_eq!(
+            result.content[1],
+            ToolResultBlock::Image(Image::from_url("https://example.com/a.png"))
+        );
+        assert!(matches!(
+            &result.content[2],
+            ToolResultBlock::SearchResult(SearchResult { title, .. }) if title == "Example"
+        ));
+        assert!(matches!(result.content[3], ToolResultBlock::Unknown(_)));
+
+        let result: ToolResult = serde_json::from_value(json!({
+            "tool_use_id": "toolu_2",
+            "content": null,
+            "is_error": false
+        }))
+        .unwrap();
+        assert!(result.content.is_empty());
+
+        // Blocks of a known type are parsed strictly
+        let error = serde_json::from_value::<ToolResult>(json!({
+            "tool_use_id": "toolu_2",
+            "content": [{"type": "search_result", "source": "https://example.com"}]
+        }))
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("missing field `title`"),
+            "{error}"
+        );
+
+        let result = ToolResultBuilder::default()
+            .tool_use_id("toolu_3")
+            .content("Done")
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(MessageContent::from(result)).unwrap(),
+            json!({
+                "type": "tool_result",
+                "tool_use_id": "toolu_3",
+                "content": [{"type": "text", "text": "Done"}],
+                "is_error": false
+            })
+        );
+
+        // Text blocks can't be empty, so empty tool output is left out
+        let result = ToolResultBuilder::default()
+            .tool_use_id("toolu_4")
+            .content("")
+            .build()
+            .unwrap();
+        assert!(result.content.is_empty());
+        assert_eq!(
+            serde_json::to_value(MessageContent::from(result)).unwrap(),
+            json!({"type": "tool_result", "tool_use_id": "toolu_4", "is_error": false})
+        );
+    }
+
+    #[test]
+    fn test_image_from_bytes() {
+        assert_eq!(
+            ImageMediaType::sniff(b"\x89PNG\r\n\x1a\n\0\0"),
+            Some(ImageMediaType::Png)
+        );
+        assert_eq!(
+            ImageMediaType::sniff(b"\xff\xd8\xff\xe0"),
+            Some(ImageMediaType::Jpeg)
+        );
+        assert_eq!(ImageMediaType::sniff(b"GIF89a"), Some(ImageMediaType::Gif));
+        assert_eq!(
+            ImageMediaType::sniff(b"RIFF\x10\0\0\0WEBPVP8 "),
+            Some(ImageMediaType::Webp)
+        );
+        assert_eq!(ImageMediaType::sniff(b"RIFF\x10\0\0\0WAVE"), None);
+
+        let image = Image::from_bytes(b"GIF87a").unwrap();
+        assert_eq!(
+            serde_json::to_value(MessageContent::from(image)).unwrap(),
+            json!({
+                "type": "image",
+                "source": {
+                    "type": "base64",
+                    "media_type": "image/gif",
+                    "data": "R0lGODdh"
+                }
+            })
+        );
+
+        assert!(matches!(
+            Image::from_bytes(b"%PDF-1.7"),
+            Err(AnthropicError::InvalidImage(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_image_from_path() {
+        let error = Image::from_path(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let error = Image::from_path("does-not-exist.png").await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_deserialize_response() {
         let response = json!({
@@ -1166,7 +2435,15 @@ mod tests {
             "input_tokens": 10,
             "cache_creation_input_tokens": 0,
             "cache_read_input_tokens": 0,
-            "output_tokens": 12
+            "cache_creation": {
+              "ephemeral_5m_input_tokens": 0,
+              "ephemeral_1h_input_tokens": 0
+            },
+            "output_tokens": 12,
+            "server_tool_use": {
+              "web_search_requests": 1
+            },
+            "service_tier": "standard"
           }
         })
         .to_string();
@@ -1177,6 +2454,23 @@ mod tests {
 
         assert_eq!(usage.input_tokens, Some(10));
         assert_eq!(usage.output_tokens, Some(12));
+        assert_eq!(usage.cache_creation_input_tokens, Some(0));
+        assert_eq!(usage.cache_read_input_tokens, Some(0));
+        assert_eq!(
+            usage.cache_creation,
+            Some(CacheCreation {
+                ephemeral_5m_input_tokens: Some(0),
+                ephemeral_1h_input_tokens: Some(0),
+            })
+        );
+        assert_eq!(
+            usage
+                .server_tool_use
+                .as_ref()
+                .and_then(|usage| usage.web_search_requests),
+            Some(1)
+        );
+        assert_eq!(usage.service_tier.as_deref(), Some("standard"));
         assert_eq!(
             response.id,
             Some("msg_01KkaCASJuaAgTWD2wqdbwC8".to_string())
@@ -1185,7 +2479,7 @@ mod tests {
             response.model,
             Some("claude-3-5-sonnet-20241022".to_string())
         );
-        assert_eq!(response.stop_reason, Some("end_turn".to_string()));
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
         assert_eq!(response.stop_sequence, None);
         assert_eq!(
             response
@@ -1196,11 +2490,241 @@ mod tests {
                 .first()
                 .unwrap()
                 .as_text(),
-            Some(&Text {
-                text: "Hi! How can I help you today?".to_string(),
-                cache_control: None,
-            })
+            Some(&Text::from("Hi! How can I help you today?"))
+        );
+    }
+
+    #[test]
+    fn test_add_usage() {
+        let mut usage = Usage {
+            input_tokens: Some(10),
+            output_tokens: Some(5),
+            cache_read_input_tokens: Some(100),
+            service_tier: Some("standard".to_string()),
+            ..Default::default()
+        };
+        usage += Usage {
+            input_tokens: Some(20),
+            output_tokens: Some(7),
+            cache_creation: Some(CacheCreation {
+                ephemeral_5m_input_tokens: Some(50),
+                ephemeral_1h_input_tokens: None,
+            }),
+            server_tool_use: Some(ServerToolUsage {
+                web_search_requests: Some(2),
+            }),
+            ..Default::default()
+        };
+        let usage = usage + Usage::default();
+
+        assert_eq!(
+            usage,
+            Usage {
+                input_tokens: Some(30),
+                output_tokens: Some(12),
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: Some(100),
+                cache_creation: Some(CacheCreation {
+                    ephemeral_5m_input_tokens: Some(50),
+                    ephemeral_1h_input_tokens: None,
+                }),
+                server_tool_use: Some(ServerToolUsage {
+                    web_search_requests: Some(2),
+                }),
+                service_tier: Some("standard".to_string()),
+            }
+        );
+
+        let overflowing = Usage {
+            output_tokens: Some(u32::MAX),
+            ..Default::default()
+        } + usage;
+        assert_eq!(overflowing.output_tokens, Some(u32::MAX));
+    }
+
+    #[test]
+    fn test_stop_reason_roundtrip() {
+        let reasons: Vec<StopReason> =
+            serde_json::from_value(json!(["tool_use", "pause_turn", "something_new"])).unwrap();
+
+        assert_eq!(
+            reasons,
+            vec![
+                StopReason::ToolUse,
+                StopReason::PauseTurn,
+                StopReason::Other("something_new".to_string())
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&reasons).unwrap(),
+            json!(["tool_use", "pause_turn", "something_new"])
+        );
+    }
+
+    #[test]
+    fn test_server_tool_blocks_roundtrip() {
+        let content = json!([
+            {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {"query": "rust"}},
+            {"type": "web_search_tool_result", "tool_use_id": "srvtoolu_1", "content": [
+                {"type": "web_search_result", "url": "https://www.rust-lang.org", "title": "Rust", "encrypted_content": "EqgfCioIARgB", "page_age": "April 30, 2025"}
+            ]},
+            {"type": "web_search_tool_result", "tool_use_id": "srvtoolu_2", "content": {
+                "type": "web_search_tool_result_error", "error_code": "max_uses_exceeded"
+            }},
+            {"type": "server_tool_use", "id": "srvtoolu_3", "name": "code_execution", "input": {"code": "print(1)"}},
+            {"type": "code_execution_tool_result", "tool_use_id": "srvtoolu_3", "content": {
+                "type": "code_execution_result", "stdout": "1\n", "stderr": "", "return_code": 0,
+                "content": [{"type": "code_execution_output", "file_id": "file_1"}]
+            }},
+            {"type": "code_execution_tool_result", "tool_use_id": "srvtoolu_4", "content": {
+                "type": "code_execution_tool_result_error", "error_code": "unavailable"
+            }}
+        ]);
+
+        let blocks: Vec<MessageContent> = serde_json::from_value(content.clone()).unwrap();
+
+        let MessageContent::WebSearchToolResult(search) = &blocks[1] else {
+            panic!("expected a web search result, got {:?}", blocks[1]);
+        };
+        let WebSearchToolResultContent::Results(results) = &search.content else {
+            panic!("expected results, got {:?}", search.content);
+        };
+        assert_eq!(results[0].url, "https://www.rust-lang.org");
+
+        assert!(matches!(
+            &blocks[2],
+            MessageContent::WebSearchToolResult(WebSearchToolResult {
+                content: WebSearchToolResultContent::Error(error),
+                ..
+            }) if error.error_code == "max_uses_exceeded"
+        ));
+
+        let MessageContent::CodeExecutionToolResult(execution) = &blocks[4] else {
+            panic!("expected a code execution result, got {:?}", blocks[4]);
+        };
+        let CodeExecutionToolResultContent::CodeExecutionResult(result) = &execution.content else {
+            panic!("expected a result, got {:?}", execution.content);
+        };
+        assert_eq!(result.stdout, "1\n");
+        assert_eq!(result.content[0].file_id, "file_1");
+
+        assert!(blocks
+            .iter()
+            .all(|block| !matches!(block, MessageContent::Unknown(_))));
+        assert_eq!(serde_json::to_value(&blocks).unwrap(), content);
+    }
+
+    #[test]
+    fn test_resolve_citations() {
+        let request: CreateMessagesRequest = serde_json::from_value(json!({
+            "model": "claude",
+            "max_tokens": 1024,
+            "stream": false,
+            "messages": [{"role": "user", "content": [
+                {"type": "document", "source": {"type": "text", "media_type": "text/plain", "data": "Grüße! The grass is green."}},
+                {"type": "document", "source": {"type": "content", "content": [
+                    {"type": "text", "text": "First"},
+                    {"type": "text", "text": "Second"}
+                ]}},
+                {"type": "document", "source": {"type": "file", "file_id": "file_1"}},
+                {"type": "text", "text": "What color is the grass?"}
+            ]}]
+        }))
+        .unwrap();
+
+        let text: Text = serde_json::from_value(json!({
+            "type": "text",
+            "text": "the grass is green",
+            "citations": [
+                {"type": "char_location", "cited_text": "The grass is green.", "document_index": 0, "document_title": null, "start_char_index": 7, "end_char_index": 26},
+                {"type": "content_block_location", "cited_text": "Second", "document_index": 1, "start_block_index": 1, "end_block_index": 2},
+                {"type": "page_location", "cited_text": "Green", "document_index": 2, "start_page_number": 2, "end_page_number": 3},
+                {"type": "web_search_result_location", "cited_text": "Green", "url": "https://example.com", "title": "Grass", "encrypted_index": "Eo8B"}
+            ]
+        }))
+        .unwrap();
+        let citations = text.citations.unwrap();
+
+        assert_eq!(
+            citations[0].resolve(&request),
+            Some(CitedSource::Text("The grass is green."))
         );
+        assert!(matches!(
+            citations[1].resolve(&request),
+            Some(CitedSource::Blocks([ContentBlockSourceContent::Text { text, .. }])) if text == "Second"
+        ));
+        assert_eq!(
+            citations[2].resolve(&request),
+            Some(CitedSource::Pages(2..3))
+        );
+        assert_eq!(citations[3].resolve(&request), None);
+        assert_eq!(citations[3].cited_text(), Some("Green"));
+    }
+
+    #[test]
+    fn test_unknown_content_roundtrip() {
+        let block = json!({"type": "container_upload", "file_id": "file_1", "extra": [1, 2]});
+        let content: MessageContent = serde_json::from_value(block.clone()).unwrap();
+
+        assert_eq!(content, MessageContent::Unknown(block.clone()));
+        assert_eq!(serde_json::to_value(&content).unwrap(), block);
+
+        let delta = json!({"type": "compaction_delta", "summary": "..."});
+        let parsed: ContentBlockDelta = serde_json::from_value(delta.clone()).unwrap();
+        assert_eq!(parsed, ContentBlockDelta::Unknown(delta.clone()));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), delta);
+
+        let event = json!({"type": "message_pause", "reason": "thinking"});
+        let parsed: MessagesStreamEvent = serde_json::from_value(event.clone()).unwrap();
+        assert_eq!(parsed, MessagesStreamEvent::Unknown(event.clone()));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), event);
+
+        // Known types are not affected
+        let text: MessageContent =
+            serde_json::from_value(json!({"type": "text", "text": "Hi"})).unwrap();
+        assert_eq!(text, "Hi".into());
+
+        // Streamed with the type first, or buffered if it comes later
+        let text: MessageContent =
+            serde_json::from_str(r#"{"type": "text", "text": "Hi"}"#).unwrap();
+        assert_eq!(text, "Hi".into());
+        let text: MessageContent =
+            serde_json::from_str(r#"{"text": "Hi", "type": "text"}"#).unwrap();
+        assert_eq!(text, "Hi".into());
+        let content: MessageContent = serde_json::from_str(
+            r#"{"type": "container_upload", "file_id": "file_1", "extra": [1, 2]}"#,
+        )
+        .unwrap();
+        assert_eq!(content, MessageContent::Unknown(block));
+    }
+
+    #[test]
+    fn test_malformed_known_content_is_an_error() {
+        let error = serde_json::from_value::<MessageContent>(json!({"type": "text"})).unwrap_err();
+        assert!(
+            error.to_string().contains("missing field `text`"),
+            "{error}"
+        );
+
+        let delta = json!({"type": "text_delta", "text": 1});
+        assert!(serde_json::from_value::<ContentBlockDelta>(delta).is_err());
+
+        let event = json!({
+            "type": "message_delta",
+            "delta": {"stop_reason": "end_turn"},
+            "usage": {"output_tokens": "many"}
+        });
+        assert!(serde_json::from_value::<MessagesStreamEvent>(event).is_err());
+
+        let event = json!({"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use"}});
+        assert!(serde_json::from_value::<MessagesStreamEvent>(event).is_err());
+
+        let citation =
+            json!({"type": "char_location", "cited_text": "Hi", "start_char_index": "0"});
+        assert!(serde_json::from_value::<Citation>(citation).is_err());
+
+        // Values without a type are malformed as well
+        assert!(serde_json::from_value::<MessageContent>(json!({"text": "Hi"})).is_err());
     }
 
     #[test_log::test(tokio::test)]
@@ -1211,10 +2735,7 @@ mod tests {
             message,
             Message {
                 role: MessageRole::User,
-                content: MessageContentList(vec![MessageContent::Text(Text {
-                    text: "Hello world!".to_string(),
-                    cache_control: None,
-                })]),
+                content: MessageContentList(vec![MessageContent::Text(Text::from("Hello world!"))]),
             }
         );
 
@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+
+use async_anthropic::{
+    agent::AgentRunner,
+    tools::{ToolRegistry, TypedTool},
+    types::{
+        CreateMessagesRequestBuilder, MessageContent, MessageRole, StopReason, ToolInputSchema,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[async_trait]
+pub trait MockApp {
+    async fn setup() -> MockServer;
+}
+
+struct TestSetup;
+
+#[async_trait]
+impl MockApp for TestSetup {
+    async fn setup() -> MockServer {
+        MockServer::start().await
+    }
+}
+
+#[derive(Deserialize)]
+struct WeatherInput {
+    location: String,
+}
+
+struct GetWeather;
+
+impl TypedTool for GetWeather {
+    type Input = WeatherInput;
+    type Error = String;
+
+    fn name(&self) -> &str {
+        "get_weather"
+    }
+
+    fn input_schema(&self) -> ToolInputSchema {
+        ToolInputSchema::default()
+    }
+
+    async fn call(&self, input: WeatherInput) -> Result<String, String> {
+        Ok(format!("Pretty warm in {}", input.location))
+    }
+}
+
+async fn mount_final_response(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_partial_json(json!({
+            "messages": [{"role": "user"}, {"role": "assistant"}, {"role": "user", "content": [{
+                "type": "tool_result",
+                "tool_use_id": "toolu_1",
+                "content": [{"type": "text", "text": "Pretty warm in San Francisco"}],
+                "is_error": false
+            }]}]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "msg_2",
+            "content": [{"type": "text", "text": "It is pretty warm."}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 20, "output_tokens": 5}
+        })))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+async fn mount_tool_use_response(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_partial_json(json!({
+            "tools": [{"type": "custom", "name": "get_weather"}]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "msg_1",
+            "content": [{
+                "type": "tool_use",
+                "id": "toolu_1",
+                "name": "get_weather",
+                "input": {"location": "San Francisco"}
+            }],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 3}
+        })))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_agent_runs_tools_until_end_turn() {
+    let server = TestSetup::setup().await;
+    mount_final_response(&server).await;
+    mount_tool_use_response(&server).await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["What is the weather like in San Francisco?".into()])
+        .build()
+        .unwrap();
+
+    let turns = Arc::new(Mutex::new(Vec::new()));
+    let run = AgentRunner::new(&client, ToolRegistry::new().with_tool(GetWeather))
+        .on_turn({
+            let turns = turns.clone();
+            move |turn| turns.lock().unwrap().push(turn.tool_results.len())
+        })
+        .run(request)
+        .await
+        .unwrap();
+
+    assert_eq!(run.iterations, 2);
+    assert_eq!(*turns.lock().unwrap(), vec![1, 0]);
+    assert_eq!(run.messages.len(), 4);
+    assert_eq!(run.messages[3].role, MessageRole::Assistant);
+    assert_eq!(
+        run.final_response.content,
+        vec![MessageContent::Text("It is pretty warm.".into())]
+    );
+    assert_eq!(run.usage.input_tokens, Some(30));
+    assert_eq!(run.usage.output_tokens, Some(8));
+}
+
+#[tokio::test]
+async fn test_agent_stops_at_max_iterations() {
+    let server = TestSetup::setup().await;
+    mount_tool_use_response(&server).await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["What is the weather like in San Francisco?".into()])
+        .build()
+        .unwrap();
+
+    let run = AgentRunner::new(&client, ToolRegistry::new().with_tool(GetWeather))
+        .with_max_iterations(1)
+        .run(request)
+        .await
+        .unwrap();
+
+    assert_eq!(run.iterations, 1);
+    assert_eq!(run.final_response.stop_reason, Some(StopReason::ToolUse));
+    assert_eq!(run.messages.len(), 3);
+}
@@ -0,0 +1,240 @@
+use async_anthropic::{
+    types::{
+        CreateMessagesRequestBuilder, MessageBatchProcessingStatus, MessageBatchRequestBuilder,
+        PaginationParamsBuilder,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use serde_json::json;
+use tokio_stream::StreamExt as _;
+use wiremock::{
+    matchers::{body_partial_json, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[async_trait]
+pub trait MockApp {
+    async fn setup() -> MockServer;
+}
+
+struct TestSetup;
+
+#[async_trait]
+impl MockApp for TestSetup {
+    async fn setup() -> MockServer {
+        MockServer::start().await
+    }
+}
+
+fn batch_json(id: &str, processing_status: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "type": "message_batch",
+        "processing_status": processing_status,
+        "request_counts": {
+            "processing": 0,
+            "succeeded": 2,
+            "errored": 1,
+            "canceled": 0,
+            "expired": 0
+        },
+        "ended_at": null,
+        "created_at": "2024-09-24T18:37:24.100435Z",
+        "expires_at": "2024-09-25T18:37:24.100435Z",
+        "cancel_initiated_at": null,
+        "results_url": null
+    })
+}
+
+#[tokio::test]
+async fn test_create_batch() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches"))
+        .and(body_partial_json(json!({
+            "requests": [{"custom_id": "first", "params": {"model": "test-model", "stream": false}}]
+        })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(batch_json("msgbatch_1", "in_progress")),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = MessageBatchRequestBuilder::default()
+        .custom_id("first")
+        .params(
+            CreateMessagesRequestBuilder::default()
+                .model("test-model")
+                .messages(vec!["Hello world!".into()])
+                .stream(true)
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+
+    let batch = client.batches().create(vec![request]).await.unwrap();
+
+    assert_eq!(batch.id, "msgbatch_1");
+    assert_eq!(
+        batch.processing_status,
+        MessageBatchProcessingStatus::InProgress
+    );
+    assert_eq!(batch.request_counts.errored, 1);
+}
+
+#[tokio::test]
+async fn test_list_batches_with_pagination() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches"))
+        .and(query_param("after_id", "msgbatch_1"))
+        .and(query_param("limit", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [batch_json("msgbatch_2", "ended")],
+            "has_more": true,
+            "first_id": "msgbatch_2",
+            "last_id": "msgbatch_2"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let params = PaginationParamsBuilder::default()
+        .after_id("msgbatch_1")
+        .limit(1u32)
+        .build()
+        .unwrap();
+
+    let page = client.batches().list(&params).await.unwrap();
+
+    assert!(page.has_more);
+    assert_eq!(page.last_id.as_deref(), Some("msgbatch_2"));
+    assert_eq!(
+        page.data[0].processing_status,
+        MessageBatchProcessingStatus::Ended
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_and_delete_batch() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches/msgbatch_1/cancel"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(batch_json("msgbatch_1", "canceling")),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("DELETE"))
+        .and(path("/v1/messages/batches/msgbatch_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "msgbatch_1",
+            "type": "message_batch_deleted"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let batch = client.batches().cancel("msgbatch_1").await.unwrap();
+    assert_eq!(
+        batch.processing_status,
+        MessageBatchProcessingStatus::Canceling
+    );
+
+    let deleted = client.batches().delete("msgbatch_1").await.unwrap();
+    assert_eq!(deleted.id, "msgbatch_1");
+}
+
+#[tokio::test]
+async fn test_stream_batch_results() {
+    let server = TestSetup::setup().await;
+
+    let lines = [
+        json!({"custom_id": "first", "result": {"type": "succeeded", "message": {
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "test-model",
+            "content": [{"type": "text", "text": "Hi!"}],
+            "stop_reason": "end_turn", "stop_sequence": null,
+            "usage": {"input_tokens": 10, "output_tokens": 3}
+        }}}),
+        json!({"custom_id": "second", "result": {"type": "errored", "error": {
+            "type": "error",
+            "error": {"type": "invalid_request_error", "message": "max_tokens is too large"}
+        }}}),
+        json!({"custom_id": "third", "result": {"type": "expired"}}),
+    ];
+    let body = lines
+        .iter()
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches/msgbatch_1/results"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("request-id", "req_results")
+                .set_body_raw(body.clone(), "application/binary"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let (results, meta) = client
+        .batches()
+        .results_with_meta("msgbatch_1")
+        .await
+        .unwrap()
+        .into_parts();
+    let results = results.collect::<Result<Vec<_>, _>>().await.unwrap();
+
+    assert_eq!(meta.request_id.as_deref(), Some("req_results"));
+    assert_eq!(meta.body_size(), body.len());
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].custom_id, "first");
+
+    let mut results = results.into_iter().map(|result| result.into_result());
+
+    let message = results.next().unwrap().unwrap();
+    assert_eq!(
+        message.content[0].as_text().map(|text| text.text.as_str()),
+        Some("Hi!")
+    );
+
+    let error = results.next().unwrap().unwrap_err();
+    assert_eq!(error.error_type, "invalid_request_error");
+
+    let error = results.next().unwrap().unwrap_err();
+    assert_eq!(error.error_type, "expired");
+}
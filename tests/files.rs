@@ -0,0 +1,158 @@
+use async_anthropic::{
+    files::{FileUpload, FILES_API_BETA},
+    types::{Document, DocumentSource, PaginationParams},
+    Client,
+};
+use async_trait::async_trait;
+use serde_json::json;
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[async_trait]
+pub trait MockApp {
+    async fn setup() -> MockServer;
+}
+
+struct TestSetup;
+
+#[async_trait]
+impl MockApp for TestSetup {
+    async fn setup() -> MockServer {
+        MockServer::start().await
+    }
+}
+
+fn file_json(id: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "type": "file",
+        "filename": "report.pdf",
+        "mime_type": "application/pdf",
+        "size_bytes": 7,
+        "created_at": "2025-04-14T12:00:00Z",
+        "downloadable": false
+    })
+}
+
+#[tokio::test]
+async fn test_upload_file() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/files"))
+        .and(header("anthropic-beta", FILES_API_BETA))
+        .and(|request: &wiremock::Request| {
+            let body = String::from_utf8_lossy(&request.body);
+            body.contains("name=\"file\"; filename=\"report.pdf\"")
+                && body.contains("Content-Type: application/pdf")
+                && body.contains("%PDF-1.")
+        })
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("request-id", "req_upload")
+                .set_body_json(file_json("file_1")),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let upload = FileUpload::from_reader("report.pdf", "application/pdf", &b"%PDF-1."[..])
+        .await
+        .unwrap();
+
+    let response = client.files().upload_with_meta(upload).await.unwrap();
+    assert_eq!(response.meta.request_id.as_deref(), Some("req_upload"));
+
+    let file = response.into_data();
+
+    assert_eq!(file.id, "file_1");
+    assert_eq!(file.size_bytes, 7);
+
+    let document = serde_json::to_value(Document {
+        source: DocumentSource::File { file_id: file.id },
+        title: None,
+        context: None,
+        citations: None,
+        cache_control: None,
+    })
+    .unwrap();
+    assert_eq!(
+        document,
+        json!({"source": {"type": "file", "file_id": "file_1"}})
+    );
+}
+
+#[tokio::test]
+async fn test_list_get_download_and_delete_files() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/files"))
+        .and(header("anthropic-beta", FILES_API_BETA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [file_json("file_1")],
+            "has_more": false,
+            "first_id": "file_1",
+            "last_id": "file_1"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/files/file_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file_1")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/files/file_1/content"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"%PDF-1.".to_vec()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("DELETE"))
+        .and(path("/v1/files/file_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "file_1",
+            "type": "file_deleted"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let files = client
+        .files()
+        .list(&PaginationParams::default())
+        .await
+        .unwrap();
+    assert_eq!(files.data.len(), 1);
+    assert!(!files.has_more);
+
+    let file = client.files().get("file_1").await.unwrap();
+    assert_eq!(file.filename, "report.pdf");
+
+    let content = client.files().download_with_meta("file_1").await.unwrap();
+    assert_eq!(content.data, b"%PDF-1.");
+    assert_eq!(content.meta.status, 200);
+    assert_eq!(content.meta.body_size(), 7);
+
+    let deleted = client.files().delete("file_1").await.unwrap();
+    assert_eq!(deleted.id, "file_1");
+}
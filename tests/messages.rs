@@ -1,6 +1,11 @@
 use async_anthropic::{
-    errors::{AnthropicError, ApiError},
-    types::{CreateMessagesRequestBuilder, MessageBuilder, MessageContent, MessageRole},
+    accumulator::MessagesStreamExt as _,
+    errors::{AnthropicError, ApiError, ApiErrorKind},
+    rate_limit::RateLimiter,
+    retry::DefaultRetryPolicy,
+    types::{
+        CreateMessagesRequestBuilder, MessageBuilder, MessageContent, MessageRole, StopReason,
+    },
     Client,
 };
 use async_trait::async_trait;
@@ -211,6 +216,87 @@ async fn test_default_backoff_retries() {
     }
 }
 
+#[tokio::test]
+async fn test_server_errors_are_retried() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(503)
+                .insert_header("retry-after-ms", "5")
+                .set_body_json(json!({
+                    "type": "error",
+                    "error": {"type": "api_error", "message": "Internal server error"}
+                })),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{"type": "text", "text": "retried response"}]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let response = client.messages().create(request).await.unwrap();
+
+    assert_eq!(response.content, vec!["retried response".into()]);
+}
+
+#[tokio::test]
+async fn test_retry_policy_max_attempts() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(529)
+                .insert_header("retry-after-ms", "5")
+                .set_body_json(json!({
+                    "type": "error",
+                    "error": {"type": "overloaded_error", "message": "Overloaded"}
+                })),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .retry_policy(DefaultRetryPolicy::default().with_max_attempts(2))
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let error = client.messages().create(request).await.unwrap_err();
+
+    assert!(matches!(error, AnthropicError::RateLimit { .. }));
+}
+
 #[tokio::test]
 async fn test_error_handling_bad_request() {
     let server = TestSetup::setup().await;
@@ -308,3 +394,460 @@ async fn test_error_handling_unauthorized() {
         &result
     )
 }
+
+#[tokio::test]
+async fn test_collect_streamed_response() {
+    let server = TestSetup::setup().await;
+    let secret_key = "test_secret";
+
+    let events = [
+        json!({"type": "message_start", "message": {
+            "id": "msg_1", "model": "test-model", "role": "assistant", "content": [],
+            "usage": {"input_tokens": 10, "output_tokens": 1}
+        }}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "streamed "}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "response"}}),
+        json!({"type": "content_block_stop", "index": 0}),
+        json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 5}}),
+        json!({"type": "message_stop"}),
+    ];
+    let body = events
+        .iter()
+        .map(|event| {
+            format!(
+                "event: {}\ndata: {event}\n\n",
+                event["type"].as_str().unwrap()
+            )
+        })
+        .collect::<String>();
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key(secret_key)
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model".to_string())
+        .messages(vec![MessageBuilder::default()
+            .role(MessageRole::User)
+            .content("Hello world!")
+            .build()
+            .unwrap()])
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .create_stream(request)
+        .await
+        .collect_response()
+        .await
+        .unwrap();
+
+    assert_eq!(response.id.as_deref(), Some("msg_1"));
+    assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+    assert_eq!(
+        response.content[0].as_text().map(|text| text.text.as_str()),
+        Some("streamed response")
+    );
+    let usage = response.usage.unwrap();
+    assert_eq!(usage.input_tokens, Some(10));
+    assert_eq!(usage.output_tokens, Some(5));
+}
+
+#[tokio::test]
+async fn test_count_tokens_strips_generation_fields() {
+    let server = TestSetup::setup().await;
+    let secret_key = "test_secret";
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/count_tokens"))
+        .and(|request: &wiremock::Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body.get("max_tokens").is_none()
+                && body.get("stream").is_none()
+                && body["system"] == "Be brief"
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"input_tokens": 14})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key(secret_key)
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model".to_string())
+        .stream(true)
+        .system("Be brief".to_string())
+        .messages(vec![MessageBuilder::default()
+            .role(MessageRole::User)
+            .content("Hello world!")
+            .build()
+            .unwrap()])
+        .build()
+        .unwrap();
+
+    let result = client.messages().count_tokens(request).await.unwrap();
+
+    assert_eq!(result.input_tokens, 14);
+}
+
+#[cfg(feature = "schemars")]
+#[derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+struct Weather {
+    location: String,
+    temperature: f64,
+}
+
+#[cfg(feature = "schemars")]
+#[tokio::test]
+async fn test_create_structured() {
+    use wiremock::matchers::body_partial_json;
+
+    let server = TestSetup::setup().await;
+    let secret_key = "test_secret";
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(body_partial_json(json!({
+            "output_config": {"format": {"type": "json_schema", "schema": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["location", "temperature"]
+            }}}
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{"type": "text", "text": "{\"location\": \"SF\", \"temperature\": 21.5}"}]
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{"type": "text", "text": "It is 21.5 degrees in SF"}]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key(secret_key)
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model".to_string())
+        .messages(vec![MessageBuilder::default()
+            .role(MessageRole::User)
+            .content("What is the weather like in San Francisco?")
+            .build()
+            .unwrap()])
+        .build()
+        .unwrap();
+
+    let result = client
+        .messages()
+        .create_structured::<Weather>(request.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result.output,
+        Weather {
+            location: "SF".to_string(),
+            temperature: 21.5
+        }
+    );
+
+    let result = client
+        .messages()
+        .create_structured::<Weather>(request)
+        .await;
+
+    assert!(
+        matches!(
+            &result,
+            Err(AnthropicError::StructuredOutput { raw, .. }) if raw == "It is 21.5 degrees in SF"
+        ),
+        "actual: {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_rate_limit_headers() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("anthropic-ratelimit-requests-limit", "50")
+                .insert_header("anthropic-ratelimit-requests-remaining", "49")
+                .insert_header("anthropic-ratelimit-input-tokens-remaining", "39990")
+                .insert_header(
+                    "anthropic-ratelimit-input-tokens-reset",
+                    "2025-01-01T00:00:01Z",
+                )
+                .set_body_json(json!({
+                    "content": [{"type": "text", "text": "Hi!"}]
+                })),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .rate_limiter(RateLimiter::new(50, 40_000))
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    client.messages().create(request.clone()).await.unwrap();
+    let response = client.messages().create_with_meta(request).await.unwrap();
+
+    let rate_limit = response.meta.rate_limit.unwrap();
+    assert_eq!(rate_limit.requests.limit, Some(50));
+    assert_eq!(rate_limit.requests.remaining, Some(49));
+    assert_eq!(rate_limit.input_tokens.remaining, Some(39990));
+    assert_eq!(
+        rate_limit.input_tokens.reset.as_deref(),
+        Some("2025-01-01T00:00:01Z")
+    );
+}
+
+#[tokio::test]
+async fn test_response_meta() {
+    let server = TestSetup::setup().await;
+
+    let body = json!({"id": "msg_1", "content": [{"type": "text", "text": "Hi!"}]});
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("request-id", "req_1")
+                .insert_header("anthropic-ratelimit-requests-remaining", "49")
+                .set_body_json(&body),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let response = client.messages().create_with_meta(request).await.unwrap();
+
+    assert_eq!(response.id.as_deref(), Some("msg_1"));
+    assert_eq!(response.meta.status, 200);
+    assert_eq!(response.meta.request_id.as_deref(), Some("req_1"));
+    assert_eq!(
+        response
+            .meta
+            .rate_limit
+            .as_ref()
+            .unwrap()
+            .requests
+            .remaining,
+        Some(49)
+    );
+    assert_eq!(
+        response.meta.body_size(),
+        serde_json::to_vec(&body).unwrap().len()
+    );
+}
+
+#[tokio::test]
+async fn test_stream_response_meta() {
+    let server = TestSetup::setup().await;
+
+    let body = "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_1\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\nevent: message_stop\ndata: {\"type\": \"message_stop\"}\n\n";
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .insert_header("request-id", "req_1")
+                .set_body_string(body),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .create_stream_with_meta(request)
+        .await
+        .unwrap();
+    assert_eq!(response.meta.request_id.as_deref(), Some("req_1"));
+
+    let (stream, meta) = response.into_parts();
+    let message = stream.collect_response().await.unwrap();
+
+    assert_eq!(message.id.as_deref(), Some("msg_1"));
+    assert_eq!(meta.body_size(), body.len());
+}
+
+#[tokio::test]
+async fn test_api_error_request_id() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .insert_header("request-id", "req_1")
+                .set_body_json(json!({
+                    "type": "error",
+                    "error": {"type": "invalid_request_error", "message": "Bad"}
+                })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let error = client.messages().create(request).await.unwrap_err();
+
+    assert!(matches!(
+        &error,
+        AnthropicError::Api(ApiError { request_id: Some(request_id), .. }) if request_id == "req_1"
+    ));
+    assert_eq!(
+        error.to_string(),
+        "api error: invalid_request_error: Bad (request id: req_1)"
+    );
+    assert_eq!(error.status(), Some(http::StatusCode::BAD_REQUEST));
+    assert_eq!(error.kind(), Some(ApiErrorKind::InvalidRequest));
+    assert!(!error.is_retryable());
+}
+
+#[tokio::test]
+async fn test_error_status_without_api_error() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(403).set_body_string("<html>Forbidden</html>"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let error = client.messages().create(request).await.unwrap_err();
+
+    assert!(matches!(
+        &error,
+        AnthropicError::Status { body, .. } if body == "<html>Forbidden</html>"
+    ));
+    assert_eq!(error.status(), Some(http::StatusCode::FORBIDDEN));
+    assert_eq!(error.kind(), Some(ApiErrorKind::Permission));
+}
+
+#[tokio::test]
+async fn test_payload_limit() {
+    let server = TestSetup::setup().await;
+    let body = r#"{"content": [], "usage": {"input_tokens": "many"}}"#;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = |payload_limit: usize| {
+        Client::builder()
+            .base_url(server.uri())
+            .api_key("test_secret")
+            .payload_limit(payload_limit)
+            .build()
+            .unwrap()
+    };
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let error = client(12).messages().create(request.clone()).await;
+    let Err(AnthropicError::Deserialization(error)) = error else {
+        panic!("expected a deserialization error, got {error:?}");
+    };
+    assert_eq!(error.payload.as_deref(), Some(r#"{"content": "#));
+    assert!(error.truncated);
+
+    let error = client(0).messages().create(request).await;
+    let Err(AnthropicError::Deserialization(error)) = error else {
+        panic!("expected a deserialization error, got {error:?}");
+    };
+    assert_eq!(error.payload, None);
+    assert_eq!(error.path.as_deref(), Some("usage.input_tokens"));
+}
@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+
+use async_anthropic::{
+    errors::AnthropicError,
+    middleware::{Middleware, Next},
+    transport::{HttpRequest, HttpResponse},
+    types::CreateMessagesRequestBuilder,
+    Client,
+};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::json;
+use tokio_stream::StreamExt as _;
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[async_trait]
+pub trait MockApp {
+    async fn setup() -> MockServer;
+}
+
+struct TestSetup;
+
+#[async_trait]
+impl MockApp for TestSetup {
+    async fn setup() -> MockServer {
+        MockServer::start().await
+    }
+}
+
+/// Sets a header and records the order it ran in and the response status
+#[derive(Debug)]
+struct Record {
+    name: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Middleware for Record {
+    fn handle<'a>(
+        &'a self,
+        mut request: HttpRequest,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<HttpResponse, AnthropicError>> {
+        request
+            .headers_mut()
+            .insert("x-middleware", self.name.parse().unwrap());
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("{} request", self.name));
+
+        Box::pin(async move {
+            let response = next.run(request).await?;
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} response {}", self.name, response.status()));
+            Ok(response)
+        })
+    }
+}
+
+/// Counts the bytes of every response body as it is read
+#[derive(Debug, Default)]
+struct CountBytes(Arc<Mutex<usize>>);
+
+impl Middleware for CountBytes {
+    fn handle<'a>(
+        &'a self,
+        request: HttpRequest,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<HttpResponse, AnthropicError>> {
+        Box::pin(async move {
+            let count = self.0.clone();
+            let response = next.run(request).await?;
+
+            Ok(response.map(|body| {
+                Box::pin(body.map(move |chunk| {
+                    if let Ok(chunk) = &chunk {
+                        *count.lock().unwrap() += chunk.len();
+                    }
+                    chunk
+                })) as _
+            }))
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_runs_in_order() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .and(header("x-middleware", "inner"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [],
+            "has_more": false,
+            "first_id": null,
+            "last_id": null
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .middleware(Record {
+            name: "outer",
+            log: log.clone(),
+        })
+        .middleware(Record {
+            name: "inner",
+            log: log.clone(),
+        })
+        .build()
+        .unwrap();
+
+    client.models().list().await.unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "outer request",
+            "inner request",
+            "inner response 200 OK",
+            "outer response 200 OK"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_middleware_wraps_streaming_responses() {
+    let server = TestSetup::setup().await;
+
+    let body = "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_1\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\nevent: message_stop\ndata: {\"type\": \"message_stop\"}\n\n";
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_string(body),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let count = Arc::new(Mutex::new(0));
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .middleware(CountBytes(count.clone()))
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("claude-3-5-sonnet-20241022")
+        .messages(vec!["Hello".into()])
+        .build()
+        .unwrap();
+
+    let events = client
+        .messages()
+        .create_stream(request)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(Result::is_ok));
+    assert_eq!(*count.lock().unwrap(), body.len());
+}
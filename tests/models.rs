@@ -66,6 +66,9 @@ async fn test_successful_get_model_request() {
             display_name: "Test Model".to_string(),
             id: "model-id".to_string(),
             model_type: "test-type".to_string(),
+            max_input_tokens: 200_000,
+            max_tokens: 64_000,
+            capabilities: Default::default(),
         }))
         .expect(1)
         .mount(&server)
@@ -0,0 +1,271 @@
+use std::sync::{Arc, Mutex};
+
+use async_anthropic::{
+    accumulator::MessagesStreamExt as _,
+    errors::AnthropicError,
+    transport::{HttpRequest, HttpResponse, HttpTransport, ResponseBody},
+    types::{CreateMessagesRequestBuilder, MessageContent},
+    Client,
+};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use serde_json::json;
+
+/// Answers every request with the same response, split into chunks
+#[derive(Debug, Clone)]
+struct FakeTransport {
+    status: u16,
+    chunks: Vec<&'static str>,
+    requests: Arc<Mutex<Vec<HttpRequest>>>,
+}
+
+impl FakeTransport {
+    fn new(status: u16, chunks: Vec<&'static str>) -> Self {
+        Self {
+            status,
+            chunks,
+            requests: Arc::default(),
+        }
+    }
+}
+
+impl HttpTransport for FakeTransport {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, AnthropicError>> {
+        self.requests.lock().unwrap().push(request);
+
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+            .collect::<Vec<_>>();
+        let body: ResponseBody = Box::pin(tokio_stream::iter(chunks));
+
+        let response = http::Response::builder()
+            .status(self.status)
+            .body(body)
+            .unwrap();
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+fn request() -> async_anthropic::types::CreateMessagesRequest {
+    CreateMessagesRequestBuilder::default()
+        .model("claude-3-5-sonnet-20241022")
+        .messages(vec!["Hello".into()])
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_create_with_custom_transport() {
+    let transport = FakeTransport::new(
+        200,
+        vec![
+            r#"{"id": "msg_1", "content": [{"type": "text", "#,
+            r#""text": "Hi!"}], "stop_reason": "end_turn"}"#,
+        ],
+    );
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .transport(transport.clone())
+        .build()
+        .unwrap();
+
+    let response = client.messages().create(request()).await.unwrap();
+
+    assert_eq!(response.id.as_deref(), Some("msg_1"));
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method(), http::Method::POST);
+    assert_eq!(
+        requests[0].uri().to_string(),
+        "https://api.anthropic.com/v1/messages"
+    );
+    assert_eq!(requests[0].headers()["x-api-key"], "test_secret");
+    assert_eq!(requests[0].headers()["content-type"], "application/json");
+
+    let body = serde_json::from_slice::<serde_json::Value>(requests[0].body()).unwrap();
+    assert_eq!(body["stream"], json!(false));
+}
+
+#[tokio::test]
+async fn test_stream_with_custom_transport() {
+    let transport = FakeTransport::new(
+        200,
+        vec![
+            "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_1\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\n",
+            "event: content_block_start\ndata: {\"type\": \"content_block_start\", \"index\": 0, \"content_block\": {\"type\": \"text\", \"text\": \"\"}}\n\nevent: content_block_delta\ndata: {\"type\": \"content_block_delta\", \"index\": 0, \"delta\": {\"type\": \"text_delta\", \"text\": \"Hi",
+            "!\"}}\n\nevent: content_block_stop\ndata: {\"type\": \"content_block_stop\", \"index\": 0}\n\n",
+            "event: message_stop\ndata: {\"type\": \"message_stop\"}\n\n",
+        ],
+    );
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .transport(transport)
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .create_stream(request())
+        .await
+        .collect_response()
+        .await
+        .unwrap();
+
+    assert_eq!(response.id.as_deref(), Some("msg_1"));
+    assert_eq!(response.content, vec!["Hi!".into()]);
+}
+
+#[tokio::test]
+async fn test_stream_with_unknown_events() {
+    let transport = FakeTransport::new(
+        200,
+        vec![
+            "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_1\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\n",
+            "event: message_annotation\ndata: {\"type\": \"message_annotation\", \"note\": \"new\"}\n\n",
+            "event: content_block_start\ndata: {\"type\": \"content_block_start\", \"index\": 0, \"content_block\": {\"type\": \"hologram\", \"frames\": 3}}\n\nevent: content_block_stop\ndata: {\"type\": \"content_block_stop\", \"index\": 0}\n\n",
+            "event: message_stop\ndata: {\"type\": \"message_stop\"}\n\n",
+        ],
+    );
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .transport(transport)
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .create_stream(request())
+        .await
+        .collect_response()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.content,
+        vec![MessageContent::Unknown(
+            json!({"type": "hologram", "frames": 3})
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_error_status_from_custom_transport() {
+    let transport = FakeTransport::new(
+        400,
+        vec![r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "Bad"}}"#],
+    );
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .transport(transport)
+        .build()
+        .unwrap();
+
+    let error = client.messages().create(request()).await.unwrap_err();
+
+    assert!(matches!(
+        error,
+        AnthropicError::Api(ref e) if e.error_type == "invalid_request_error"
+    ));
+}
+
+/// Answers each request with the next body, cutting the first one off with a
+/// transport error
+#[derive(Debug, Clone, Default)]
+struct FlakyTransport {
+    bodies: Arc<Mutex<Vec<&'static str>>>,
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    /// End the first body without an error, like a connection that is closed
+    closed: bool,
+}
+
+impl HttpTransport for FlakyTransport {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, AnthropicError>> {
+        let mut requests = self.requests.lock().unwrap();
+        requests.push(serde_json::from_slice(request.body()).unwrap());
+
+        let mut chunks = vec![Ok(Bytes::from_static(
+            self.bodies.lock().unwrap().remove(0).as_bytes(),
+        ))];
+        if requests.len() == 1 && !self.closed {
+            chunks.push(Err(AnthropicError::Transport("connection reset".into())));
+        }
+        let body: ResponseBody = Box::pin(tokio_stream::iter(chunks));
+
+        let response = http::Response::builder().status(200).body(body).unwrap();
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+#[tokio::test]
+async fn test_resume_interrupted_stream() {
+    let transport = FlakyTransport {
+        bodies: Arc::new(Mutex::new(vec![
+            "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_1\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\nevent: content_block_start\ndata: {\"type\": \"content_block_start\", \"index\": 0, \"content_block\": {\"type\": \"text\", \"text\": \"\"}}\n\nevent: content_block_delta\ndata: {\"type\": \"content_block_delta\", \"index\": 0, \"delta\": {\"type\": \"text_delta\", \"text\": \"Once upon \"}}\n\n",
+            "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_2\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\nevent: content_block_start\ndata: {\"type\": \"content_block_start\", \"index\": 0, \"content_block\": {\"type\": \"text\", \"text\": \"\"}}\n\nevent: content_block_delta\ndata: {\"type\": \"content_block_delta\", \"index\": 0, \"delta\": {\"type\": \"text_delta\", \"text\": \" a time\"}}\n\nevent: content_block_stop\ndata: {\"type\": \"content_block_stop\", \"index\": 0}\n\nevent: message_delta\ndata: {\"type\": \"message_delta\", \"delta\": {\"stop_reason\": \"end_turn\"}}\n\nevent: message_stop\ndata: {\"type\": \"message_stop\"}\n\n",
+        ])),
+        requests: Arc::default(),
+        closed: false,
+    };
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .transport(transport.clone())
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .with_resume(1)
+        .create_stream(request())
+        .await
+        .collect_response()
+        .await
+        .unwrap();
+
+    assert_eq!(response.id.as_deref(), Some("msg_1"));
+    assert_eq!(response.content, vec!["Once upon a time".into()]);
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[1]["messages"][1],
+        json!({"role": "assistant", "content": [{"type": "text", "text": "Once upon"}]})
+    );
+}
+
+#[tokio::test]
+async fn test_resume_stream_closed_inside_an_event() {
+    let transport = FlakyTransport {
+        bodies: Arc::new(Mutex::new(vec![
+            "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_1\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\nevent: content_block_start\ndata: {\"type\": \"content_block_start\", \"index\": 0, \"content_block\": {\"type\": \"text\", \"text\": \"\"}}\n\nevent: content_block_delta\ndata: {\"type\": \"content_block_delta\", \"index\": 0, \"delta\": {\"type\": \"text_delta\", \"text\": \"Once upon \"}}\n\nevent: content_block_delta\ndata: {\"type\": \"content_blo",
+            "event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"id\": \"msg_2\", \"model\": \"claude\", \"role\": \"assistant\", \"content\": []}}\n\nevent: content_block_start\ndata: {\"type\": \"content_block_start\", \"index\": 0, \"content_block\": {\"type\": \"text\", \"text\": \"\"}}\n\nevent: content_block_delta\ndata: {\"type\": \"content_block_delta\", \"index\": 0, \"delta\": {\"type\": \"text_delta\", \"text\": \" a time\"}}\n\nevent: content_block_stop\ndata: {\"type\": \"content_block_stop\", \"index\": 0}\n\nevent: message_delta\ndata: {\"type\": \"message_delta\", \"delta\": {\"stop_reason\": \"end_turn\"}}\n\nevent: message_stop\ndata: {\"type\": \"message_stop\"}\n\n",
+        ])),
+        requests: Arc::default(),
+        closed: true,
+    };
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .transport(transport.clone())
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .with_resume(1)
+        .create_stream(request())
+        .await
+        .collect_response()
+        .await
+        .unwrap();
+
+    assert_eq!(response.content, vec!["Once upon a time".into()]);
+    assert_eq!(transport.requests.lock().unwrap().len(), 2);
+}